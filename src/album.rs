@@ -0,0 +1,317 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use crate::{
+    encoding::{self, Script},
+    meta::Meta,
+};
+
+/// Facts shared by every track of an album.
+///
+/// These are derived once per group rather than per track, because a single track can't tell
+/// whether its album has twelve tracks or two discs, or whether its artist speaks for the whole
+/// album.
+#[derive(Debug, Clone, Default)]
+pub struct Album {
+    /// The album's title; every track in a group shares it.
+    pub title: Option<String>,
+
+    /// Digits needed to print the album's highest track number (never fewer than two).
+    pub track_width: usize,
+
+    /// True when the tracks of the album disagree about the artist.
+    pub compilation: bool,
+
+    /// An artist that speaks for every track: a shared album artist tag, or failing that a shared
+    /// track artist.
+    pub album_artist: Option<String>,
+
+    /// Number of discs in the album (at least one).
+    pub disc_count: u16,
+
+    /// Number of tracks in the group.
+    pub track_count: usize,
+
+    /// Total length in seconds, if every track's length is known.
+    pub duration: Option<f64>,
+
+    /// True when a directory holding one of the album's tracks also holds an image. Only looked
+    /// for when grouping is asked to.
+    pub has_art: bool,
+
+    /// How the movements of each work on the album are numbered. Tracks with movement numbers
+    /// but no work are counted together, under `None`.
+    pub works: HashMap<Option<String>, Work>,
+}
+
+/// The movement numbering of one work, as its tracks tell it.
+#[derive(Debug, Clone, Default)]
+pub struct Work {
+    /// Digits needed to print the work's highest movement number.
+    pub width: usize,
+
+    /// Why the numbers don't describe a single set of movements, if they don't.
+    pub problem: Option<String>,
+}
+
+impl Work {
+    fn from_tracks(tracks: &[&Meta]) -> Self {
+        let numbers: Vec<_> = tracks
+            .iter()
+            .filter_map(|meta| meta.movement_number)
+            .collect();
+        let counts: Vec<_> = tracks
+            .iter()
+            .filter_map(|meta| meta.movement_count)
+            .collect();
+        let highest = numbers.iter().chain(&counts).max().copied().unwrap_or(1);
+
+        let mut seen = HashSet::new();
+        let repeated = numbers.iter().find(|&&n| !seen.insert(n));
+        let beyond = numbers
+            .iter()
+            .find(|&&n| counts.first().is_some_and(|&count| n > count));
+        let problem = match (repeated, beyond) {
+            (Some(n), _) => Some(format!("more than one track is movement {n}")),
+            _ if counts.iter().any(|&count| count != counts[0]) => {
+                Some("tracks disagree about how many movements there are".into())
+            }
+            (_, Some(n)) => Some(format!("movement {n} of a work with {}", counts[0])),
+            _ => None,
+        };
+
+        Self {
+            width: highest.to_string().len(),
+            problem,
+        }
+    }
+}
+
+/// Extensions of image files that count as cover art.
+const ART_EXTENSIONS: &[&str] = &["bmp", "gif", "jpeg", "jpg", "png", "webp"];
+
+/// Album title, album artist, and (for tracks without an album artist) directory.
+type GroupKey<'a> = (Option<&'a str>, Option<&'a str>, Option<&'a Path>);
+
+/// Albums derived from a set of tracks, along with each track's album.
+#[derive(Debug, Clone, Default)]
+pub struct Albums {
+    albums: Vec<Album>,
+    membership: Vec<usize>,
+
+    /// For each track, why its tags look out of place among its album's, if they do.
+    odd_scripts: Vec<Option<String>>,
+}
+
+impl Albums {
+    /// Groups tracks by album.
+    ///
+    /// Tracks belong to the same album when they share an album title and album artist. Tracks
+    /// without an album artist are grouped by title and directory instead, so that two unrelated
+    /// albums called "Greatest Hits" aren't mistaken for one.
+    ///
+    /// With `find_art`, the tracks' directories are searched for cover images.
+    pub fn group<'a>(
+        tracks: impl IntoIterator<Item = (&'a Path, &'a Meta)>,
+        find_art: bool,
+    ) -> Self {
+        let mut keys: HashMap<GroupKey, usize> = HashMap::new();
+        let mut groups: Vec<Vec<(&Path, &Meta)>> = Vec::new();
+        let mut membership = Vec::new();
+
+        for (path, meta) in tracks {
+            let key = match meta.album_artist.as_deref() {
+                Some(artist) => (meta.album.as_deref(), Some(artist), None),
+                None => (meta.album.as_deref(), None, path.parent()),
+            };
+
+            let idx = *keys.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[idx].push((path, meta));
+            membership.push(idx);
+        }
+
+        let mut art = HashMap::new();
+        let albums = groups
+            .iter()
+            .map(|tracks| {
+                let metas: Vec<_> = tracks.iter().map(|(_, meta)| *meta).collect();
+                let mut album = Album::from_tracks(&metas);
+                album.has_art = find_art
+                    && tracks.iter().any(|(path, _)| {
+                        let dir = path.parent().unwrap_or(Path::new(""));
+                        *art.entry(dir).or_insert_with(|| has_art(dir))
+                    });
+                album
+            })
+            .collect();
+
+        let odd_scripts = odd_scripts(&groups, &membership);
+        Self {
+            albums,
+            membership,
+            odd_scripts,
+        }
+    }
+
+    /// The album of the track at `idx`, in the order tracks were grouped.
+    pub fn of(&self, idx: usize) -> &Album {
+        &self.albums[self.membership[idx]]
+    }
+
+    /// A warning for the track at `idx` when its tags seem to be in a different encoding or
+    /// script from the rest of its album's, which usually means some were decoded wrongly.
+    pub fn odd_script(&self, idx: usize) -> Option<&str> {
+        self.odd_scripts[idx].as_deref()
+    }
+}
+
+/// Finds the tracks of each album whose script differs from most of their album's.
+///
+/// Garbled tracks among clean ones, clean among garbled, and two non-Latin scripts in one album
+/// are flagged. Latin titles in an otherwise Japanese or Russian album are common enough to pass.
+fn odd_scripts(groups: &[Vec<(&Path, &Meta)>], membership: &[usize]) -> Vec<Option<String>> {
+    let scripts: Vec<Vec<Option<Script>>> = groups
+        .iter()
+        .map(|tracks| {
+            tracks
+                .iter()
+                .map(|(_, meta)| encoding::classify(meta))
+                .collect()
+        })
+        .collect();
+    let majorities: Vec<Option<Script>> = scripts
+        .iter()
+        .map(|scripts| {
+            let mut counts: BTreeMap<Script, usize> = BTreeMap::new();
+            for &script in scripts.iter().flatten() {
+                *counts.entry(script).or_default() += 1;
+            }
+            // Ties go to clean text, so that one garbled track of two is the one flagged.
+            counts
+                .into_iter()
+                .max_by_key(|&(script, count)| (count, script != Script::Garbled))
+                .map(|(script, _)| script)
+        })
+        .collect();
+
+    let mut positions = vec![0; groups.len()];
+    membership
+        .iter()
+        .map(|&group| {
+            let script = scripts[group][positions[group]];
+            positions[group] += 1;
+
+            let (Some(script), Some(majority)) = (script, majorities[group]) else {
+                return None;
+            };
+            let odd = script != majority
+                && (script == Script::Garbled
+                    || majority == Script::Garbled
+                    || (script != Script::Latin && majority != Script::Latin));
+            odd.then(|| {
+                format!(
+                    "tags look like {script} but most of the album's look like {majority}; \
+                     some may have been decoded with the wrong encoding"
+                )
+            })
+        })
+        .collect()
+}
+
+impl Album {
+    fn from_tracks(tracks: &[&Meta]) -> Self {
+        let highest_track = tracks
+            .iter()
+            .flat_map(|meta| meta.track.into_iter().chain(meta.total_tracks))
+            .max()
+            .unwrap_or_default();
+
+        let disc_count = tracks
+            .iter()
+            .flat_map(|meta| meta.disc.into_iter().chain(meta.total_discs))
+            .max()
+            .unwrap_or_default()
+            .max(1);
+
+        let artist = shared(tracks.iter().map(|meta| meta.artist.as_deref()));
+        let mut artists = tracks.iter().filter_map(|meta| meta.artist.as_deref());
+        let compilation = artists
+            .next()
+            .is_some_and(|first| artists.any(|other| other != first));
+        let album_artist = shared(tracks.iter().map(|meta| meta.album_artist.as_deref()))
+            .or(artist)
+            .map(From::from);
+
+        Self {
+            title: tracks.first().and_then(|meta| meta.album.clone()),
+            track_width: highest_track.to_string().len().max(2),
+            compilation,
+            album_artist,
+            disc_count,
+            track_count: tracks.len(),
+            duration: tracks.iter().map(|meta| meta.duration).sum(),
+            has_art: false,
+            works: works(tracks),
+        }
+    }
+}
+
+/// Groups the tracks that have movement numbers by work, and reads each work's numbering.
+fn works(tracks: &[&Meta]) -> HashMap<Option<String>, Work> {
+    let mut by_work: HashMap<_, Vec<_>> = HashMap::new();
+    for meta in tracks.iter().filter(|meta| meta.movement_number.is_some()) {
+        by_work.entry(meta.work.clone()).or_default().push(*meta);
+    }
+    by_work
+        .into_iter()
+        .map(|(work, tracks)| (work, Work::from_tracks(&tracks)))
+        .collect()
+}
+
+impl Album {
+    /// A name for the album in reports: "Artist - Title".
+    pub fn label(&self) -> String {
+        let title = self.title.as_deref().unwrap_or("(no album)");
+        match &self.album_artist {
+            Some(artist) => format!("{artist} - {title}"),
+            None => title.into(),
+        }
+    }
+}
+
+/// The value every item agrees on, if any.
+fn shared<'a>(mut values: impl Iterator<Item = Option<&'a str>>) -> Option<&'a str> {
+    let first = values.next()??;
+    values.all(|value| value == Some(first)).then_some(first)
+}
+
+fn has_art(dir: &Path) -> bool {
+    // A bare file name's parent is the empty path, which means the current directory.
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return false;
+    };
+
+    entries.filter_map(|entry| entry.ok()).any(|entry| {
+        let path = entry.path();
+        let is_image = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                ART_EXTENSIONS
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(extension))
+            });
+        is_image && path.is_file()
+    })
+}
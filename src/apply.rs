@@ -0,0 +1,385 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fs, io,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    interrupt,
+    log::Log,
+    plan::{Entry, Mode, Status},
+    readonly, Error, Result,
+};
+
+/// A target directory and the entries that write into it, in plan order.
+type Group<'a> = (&'a Path, Vec<usize>);
+
+/// Carries out every change in the plan, recording the outcome on each entry.
+///
+/// Work is split by the directory each operation writes into. Directories are handed out to up
+/// to `jobs` workers, but a directory's operations always run in plan order on a single worker,
+/// after the directory itself has been created; operations in different directories don't
+/// depend on each other, since the plan never targets a path another entry is vacating.
+///
+/// When copying or moving into a destination, directories that don't exist yet are built under a
+/// temporary name beside the top-most of them (`Album` for `Album/CD1`) and renamed into place
+/// once all of their files are there, so nothing watching the library sees them half-filled. All
+/// the directories under one such top are built by the same worker. If any of their files fails,
+/// the others are put back and nothing appears.
+///
+/// After Ctrl-C, workers finish the operation or staged directory in hand and start no more; the
+/// rest are marked interrupted.
+///
+/// With `clear_readonly`, read-only sources are made writable for their operations and made
+/// read-only again afterwards. Directories are cleared before any work starts, since several
+/// workers may be moving files out of the same one.
+///
+/// With a `log`, each outcome is recorded as soon as it's known: as an operation finishes, or for
+/// a staged directory, once it's published or rolled back. Work carries on when the log can't be
+/// written to, and the first such error is returned at the end.
+pub fn run(
+    entries: &mut [Entry],
+    mode: &Mode,
+    jobs: usize,
+    clear_readonly: bool,
+    log: Option<&Mutex<Log>>,
+) -> io::Result<()> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut by_dir: HashMap<&Path, usize> = HashMap::new();
+    for (idx, entry) in entries.iter().enumerate() {
+        let Some(written) = written_path(entry) else {
+            continue;
+        };
+        let dir = written.parent().unwrap_or(Path::new(""));
+        let group = *by_dir.entry(dir).or_insert_with(|| {
+            groups.push((dir, Vec::new()));
+            groups.len() - 1
+        });
+        groups[group].1.push(idx);
+    }
+
+    // Each unit is the work one worker takes on at a time: a single existing directory, or every
+    // new directory under the same missing top.
+    let mut units: Vec<(Option<&Path>, Vec<Group>)> = Vec::new();
+    let mut by_top: HashMap<&Path, usize> = HashMap::new();
+    for (dir, group) in groups {
+        let Some(top) = mode.destination().and_then(|root| missing_top(dir, root)) else {
+            units.push((None, vec![(dir, group)]));
+            continue;
+        };
+        let unit = *by_top.entry(top).or_insert_with(|| {
+            units.push((Some(top), Vec::new()));
+            units.len() - 1
+        });
+        units[unit].1.push((dir, group));
+    }
+
+    let mut cleared = Vec::new();
+    if clear_readonly {
+        let dirs: HashSet<_> = entries
+            .iter()
+            .filter(|entry| vacates(entry, mode))
+            .map(|entry| readonly::dir_of(&entry.source))
+            .collect();
+        for dir in dirs {
+            // A directory that stays read-only fails its files' operations, which say why.
+            if let Ok(Some(permissions)) = readonly::clear(dir) {
+                cleared.push((dir.to_owned(), permissions));
+            }
+        }
+    }
+
+    let next = AtomicUsize::new(0);
+    let shared: &[Entry] = entries;
+    let unlogged = Mutex::new(None);
+    let record = |results: &[(usize, Option<Result<()>>)]| {
+        let Some(log) = log else {
+            return;
+        };
+        let mut log = log.lock().unwrap();
+        for (idx, result) in results {
+            if let Some(result) = result {
+                if let Err(e) = log.record_change(&shared[*idx], mode, result.as_ref().err()) {
+                    unlogged.lock().unwrap().get_or_insert(e);
+                }
+            }
+        }
+    };
+    let results: Vec<(usize, Option<Result<()>>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, units.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    while let Some((top, groups)) = units.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        if let Some(top) = top {
+                            let staged = (!interrupt::requested())
+                                .then(|| stage(shared, groups, top, mode, clear_readonly));
+                            match staged {
+                                Some(staged) => {
+                                    record(&staged);
+                                    results.extend(staged);
+                                }
+                                None => results.extend(
+                                    groups
+                                        .iter()
+                                        .flat_map(|(_, group)| group)
+                                        .map(|&idx| (idx, None)),
+                                ),
+                            }
+                            continue;
+                        }
+                        for &idx in groups.iter().flat_map(|(_, group)| group) {
+                            let result = (!interrupt::requested())
+                                .then(|| perform(&shared[idx], None, mode, clear_readonly));
+                            results.push((idx, result));
+                            record(&results[results.len() - 1..]);
+                        }
+                    }
+                    results
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    for (dir, permissions) in cleared {
+        if let Err(e) = fs::set_permissions(&dir, permissions) {
+            eprintln!("{}: couldn't make read-only again: {e}", dir.display());
+        }
+    }
+
+    for (idx, result) in results {
+        match result {
+            Some(result) => entries[idx].complete(result),
+            None => entries[idx].status = Status::Interrupted,
+        }
+    }
+    match unlogged.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// The top-most directory between `root` and `dir` (inclusive) that doesn't exist yet, if any.
+/// `root` itself is left out, since it holds every album and staging it would leave one worker
+/// building the whole library.
+fn missing_top<'a>(dir: &'a Path, root: &Path) -> Option<&'a Path> {
+    dir.ancestors()
+        .take_while(|&ancestor| ancestor != root && !ancestor.as_os_str().is_empty())
+        .take_while(|ancestor| !ancestor.exists())
+        .last()
+}
+
+/// Whether an entry's operation takes its source away from where it is.
+fn vacates(entry: &Entry, mode: &Mode) -> bool {
+    match entry.status {
+        Status::Hardlinked => true,
+        status => status.is_change() && !matches!(mode, Mode::Copy(_)),
+    }
+}
+
+/// The path an entry's operation creates, if it has one.
+fn written_path(entry: &Entry) -> Option<&Path> {
+    match entry.status {
+        status if status.is_change() => entry.target.as_deref(),
+        Status::Hardlinked => Some(&entry.source),
+        _ => None,
+    }
+}
+
+/// Carries out the operations of the groups whose directories are, or are under, `top`, which
+/// doesn't exist yet, in a staging directory that then becomes `top`. When anything goes wrong,
+/// whatever was done is undone, and the other files report that they were held back.
+///
+/// A staging directory left behind by an earlier run that was killed is built on as it is: after
+/// a move, the files in it may be the only copies.
+fn stage(
+    entries: &[Entry],
+    groups: &[Group],
+    top: &Path,
+    mode: &Mode,
+    clear_readonly: bool,
+) -> Vec<(usize, Option<Result<()>>)> {
+    let mut name = OsString::from(".tagname-");
+    name.push(top.file_name().unwrap_or_default());
+    let staging = top.with_file_name(name);
+    let staged_dir = |dir: &Path| staging.join(dir.strip_prefix(top).unwrap());
+
+    let mut done = Vec::new();
+    let mut failure = None;
+    let created = match top.parent() {
+        Some(parent) => fs::create_dir_all(parent),
+        None => Ok(()),
+    };
+    let created = created.and_then(|()| match fs::create_dir(&staging) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists && staging.is_dir() => Ok(()),
+        created => created,
+    });
+    match created {
+        Err(e) => failure = Some((groups[0].1[0], e.into())),
+        Ok(()) => {
+            'groups: for (dir, group) in groups {
+                let into = staged_dir(dir);
+                if let Err(e) = fs::create_dir_all(&into) {
+                    failure = Some((group[0], e.into()));
+                    break;
+                }
+                for &idx in group {
+                    match perform(&entries[idx], Some(&into), mode, clear_readonly) {
+                        Ok(()) => done.push((idx, into.clone())),
+                        Err(e) => {
+                            failure = Some((idx, e));
+                            break 'groups;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let all = || groups.iter().flat_map(|(_, group)| group);
+    if failure.is_none() {
+        // Renaming a directory replaces an empty one, so make sure nothing turned up meanwhile.
+        let published = if top.exists() {
+            Err(io::ErrorKind::AlreadyExists.into())
+        } else {
+            fs::rename(&staging, top)
+        };
+        match published {
+            Ok(()) => return all().map(|&idx| (idx, Some(Ok(())))).collect(),
+            Err(e) => failure = Some((groups[0].1[0], e.into())),
+        }
+    }
+
+    let (failed, error) = failure.unwrap();
+    let mut error = Some(error);
+    let mut results: Vec<_> = all()
+        .map(|&idx| {
+            let result = match error.take_if(|_| idx == failed) {
+                Some(e) => Err(e),
+                None => Err(Error::Unpublished(top.to_owned())),
+            };
+            (idx, Some(result))
+        })
+        .collect();
+    for (idx, into) in done.iter().rev() {
+        let entry = &entries[*idx];
+        let staged = into.join(entry.target.as_deref().unwrap().file_name().unwrap());
+        let undone = match mode {
+            Mode::Copy(_) => fs::remove_file(&staged),
+            _ => move_file(&staged, &entry.source),
+        };
+        if let Err(e) = undone {
+            let slot = results.iter().position(|(other, _)| other == idx).unwrap();
+            results[slot].1 = Some(Err(e.into()));
+        }
+    }
+    // Only directories left empty go; anything that couldn't be put back stays where it is.
+    for (dir, _) in groups.iter().rev() {
+        for dir in staged_dir(dir)
+            .ancestors()
+            .take_while(|&dir| dir != staging)
+        {
+            let _ = fs::remove_dir(dir);
+        }
+    }
+    let _ = fs::remove_dir(&staging);
+    results
+}
+
+/// Carries out one entry's operation. With `staging`, the file goes there instead of into its
+/// target's directory.
+fn perform(entry: &Entry, staging: Option<&Path>, mode: &Mode, clear_readonly: bool) -> Result<()> {
+    // Only Windows marks the file itself; elsewhere the blocker is always the directory.
+    let cleared = if clear_readonly
+        && vacates(entry, mode)
+        && readonly::blocker(&entry.source).as_deref() == Some(&*entry.source)
+    {
+        readonly::clear(&entry.source)?
+    } else {
+        None
+    };
+
+    let target = entry.target.as_deref().map(|target| match staging {
+        Some(staging) => staging.join(target.file_name().unwrap()),
+        None => target.to_owned(),
+    });
+    let result = match entry.status {
+        Status::Hardlinked => link_duplicate(&entry.source, entry.duplicate.as_deref().unwrap()),
+        _ => place(&entry.source, target.as_deref().unwrap(), mode),
+    };
+    if let Some(permissions) = cleared {
+        // A hard link takes on the attributes of the file it shares data with.
+        let now = match (entry.status, &result) {
+            (_, Err(_)) => Some(&*entry.source),
+            (Status::Hardlinked, Ok(())) => None,
+            (_, Ok(())) => target.as_deref(),
+        };
+        if let Some(now) = now {
+            fs::set_permissions(now, permissions)?;
+        }
+    }
+    result
+}
+
+/// Replaces `source` with a hard link to `existing`, which has the same content.
+///
+/// The link is made under a temporary name and renamed over the source, so the source is never
+/// missing if linking fails (for instance, across filesystems).
+fn link_duplicate(source: &Path, existing: &Path) -> Result<()> {
+    let mut temp = OsString::from(source.as_os_str());
+    temp.push(".tagname-link");
+    fs::hard_link(existing, &temp)?;
+    if let Err(e) = fs::rename(&temp, source) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+fn place(source: &Path, target: &Path, mode: &Mode) -> Result<()> {
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match mode {
+        Mode::Rename => fs::rename(source, target)?,
+        Mode::Move(_) => move_file(source, target)?,
+        Mode::Copy(_) => {
+            fs::copy(source, target)?;
+            verify_copy(source, target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a copy came out the same size as its source.
+fn verify_copy(source: &Path, target: &Path) -> io::Result<()> {
+    if fs::metadata(source)?.len() != fs::metadata(target)?.len() {
+        let _ = fs::remove_file(target);
+        return Err(io::Error::other("copy came out a different size"));
+    }
+    Ok(())
+}
+
+/// Moves a file, falling back to copy and delete when it has to cross filesystems.
+fn move_file(source: &Path, target: &Path) -> io::Result<()> {
+    match fs::rename(source, target) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            fs::copy(source, target)?;
+            verify_copy(source, target)?;
+            fs::remove_file(source)
+        }
+        result => result,
+    }
+}
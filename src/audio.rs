@@ -0,0 +1,206 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{meta::Meta, mp4};
+
+/// Facts about a file's audio stream, as opposed to its tags.
+#[derive(Debug, Clone, Default)]
+pub struct Properties {
+    pub codec: Option<&'static str>,
+
+    /// Length in seconds.
+    pub duration: Option<f64>,
+
+    /// Average bitrate in kilobits per second.
+    pub bitrate: Option<u32>,
+
+    pub sample_rate: Option<u32>,
+    pub size: u64,
+}
+
+impl Properties {
+    /// Reads what the container says about its audio. Gaps are filled from the file's tags (for
+    /// length) and size (for bitrate), so an estimate is still better than nothing.
+    pub fn read(path: &Path, meta: Option<&Meta>) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+
+        let mut properties = match extension.as_str() {
+            "mp3" => mp3(path).unwrap_or_default(),
+            "flac" => flac(path),
+            "m4a" | "m4b" | "m4p" | "m4v" | "isom" | "mp4" => mp4(path),
+            _ => Self::default(),
+        };
+
+        properties.size = path.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if properties.duration.is_none() {
+            properties.duration = meta.and_then(|meta| meta.duration);
+        }
+        if properties.bitrate.is_none() {
+            properties.bitrate = properties
+                .duration
+                .filter(|&duration| duration > 0.0)
+                .map(|duration| (properties.size as f64 * 8.0 / duration / 1000.0).round() as u32);
+        }
+        properties
+    }
+}
+
+fn flac(path: &Path) -> Properties {
+    let Some(info) = metaflac::Tag::read_from_path(path)
+        .ok()
+        .and_then(|tag| tag.get_streaminfo().cloned())
+    else {
+        return Properties::default();
+    };
+
+    Properties {
+        codec: Some("FLAC"),
+        duration: (info.sample_rate > 0 && info.total_samples > 0)
+            .then(|| info.total_samples as f64 / f64::from(info.sample_rate)),
+        sample_rate: Some(info.sample_rate),
+        ..Properties::default()
+    }
+}
+
+fn mp4(path: &Path) -> Properties {
+    let codec = mp4::movie_box(path).ok().flatten().map(|moov| {
+        let kinds: Vec<_> = mp4::sample_entries(&moov)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        if kinds.contains(b"alac") {
+            "ALAC"
+        } else if kinds.contains(b"mp4a") {
+            "AAC"
+        } else {
+            "MP4"
+        }
+    });
+    let Ok(tag) = mp4ameta::Tag::read_from_path(path) else {
+        return Properties {
+            codec,
+            ..Properties::default()
+        };
+    };
+
+    Properties {
+        codec,
+        duration: tag.duration().map(|duration| duration.as_secs_f64()),
+        bitrate: tag.avg_bitrate().map(|bps| (bps + 500) / 1000),
+        sample_rate: tag.sample_rate().map(|rate| rate.hz()),
+        ..Properties::default()
+    }
+}
+
+/// Reads the first MPEG audio frame, past any ID3v2 tag.
+///
+/// A Xing or Info header in that frame gives the frame count, and so the exact length of
+/// variable-bitrate files; otherwise the first frame's bitrate is taken as the file's.
+fn mp3(path: &Path) -> io::Result<Properties> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut start = 0;
+    let mut id3 = [0; 10];
+    file.read_exact(&mut id3)?;
+    if &id3[..3] == b"ID3" {
+        let size = id3[6..]
+            .iter()
+            .fold(0u64, |size, &byte| size << 7 | u64::from(byte & 0x7f));
+        let footer = if id3[5] & 0x10 != 0 { 10 } else { 0 };
+        start = 10 + size + footer;
+    }
+
+    file.seek(SeekFrom::Start(start))?;
+    let mut buf = vec![0; 8192];
+    let read = file.read(&mut buf)?;
+    buf.truncate(read);
+
+    let Some(offset) = (0..buf.len().saturating_sub(4)).find(|&idx| {
+        buf[idx] == 0xff && buf[idx + 1] & 0xe0 == 0xe0 && header(&buf[idx..]).is_some()
+    }) else {
+        return Ok(Properties::default());
+    };
+    let frame = header(&buf[offset..]).unwrap();
+
+    // The Xing header follows the side information, whose size depends on version and channels.
+    let side_info = match (frame.mpeg1, frame.mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = offset + 4 + side_info;
+    let frames = buf
+        .get(xing..xing + 12)
+        .filter(|xing| &xing[..4] == b"Xing" || &xing[..4] == b"Info")
+        .filter(|xing| xing[7] & 1 != 0)
+        .map(|xing| u32::from_be_bytes(xing[8..12].try_into().unwrap()));
+
+    let audio_len = len.saturating_sub(start + offset as u64);
+    let samples_per_frame = if frame.mpeg1 { 1152.0 } else { 576.0 };
+    let duration = match frames {
+        Some(frames) => f64::from(frames) * samples_per_frame / f64::from(frame.sample_rate),
+        None => audio_len as f64 * 8.0 / f64::from(frame.bitrate * 1000),
+    };
+
+    Ok(Properties {
+        codec: Some("MP3"),
+        duration: Some(duration),
+        bitrate: frames.is_none().then_some(frame.bitrate),
+        sample_rate: Some(frame.sample_rate),
+        ..Properties::default()
+    })
+}
+
+struct FrameHeader {
+    mpeg1: bool,
+    mono: bool,
+    bitrate: u32,
+    sample_rate: u32,
+}
+
+/// Decodes an MPEG Layer III frame header.
+fn header(bytes: &[u8]) -> Option<FrameHeader> {
+    const BITRATES_V1: [u32; 15] = [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ];
+    const BITRATES_V2: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+    const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+    let version = (bytes[1] >> 3) & 0b11;
+    let layer = (bytes[1] >> 1) & 0b11;
+    let bitrate_index = usize::from(bytes[2] >> 4);
+    let rate_index = usize::from((bytes[2] >> 2) & 0b11);
+    if version == 0b01
+        || layer != 0b01
+        || bitrate_index == 0
+        || bitrate_index == 15
+        || rate_index == 3
+    {
+        return None;
+    }
+
+    let mpeg1 = version == 0b11;
+    let divisor = match version {
+        0b11 => 1,
+        0b10 => 2,
+        _ => 4,
+    };
+    Some(FrameHeader {
+        mpeg1,
+        mono: bytes[3] >> 6 == 0b11,
+        bitrate: if mpeg1 {
+            BITRATES_V1[bitrate_index]
+        } else {
+            BITRATES_V2[bitrate_index]
+        },
+        sample_rate: SAMPLE_RATES[rate_index] / divisor,
+    })
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use crate::{meta::Meta, native::NativeTag, walk, Result};
+
+/// What to look for beyond the tags every naming template needs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Checks {
+    /// Report files without ReplayGain track and album gain.
+    pub replaygain: bool,
+}
+
+/// Reports files with gaps in their metadata. Returns true when every file passed.
+pub fn run(paths: &[String], checks: Checks) -> Result<bool> {
+    let files = walk::expand(paths, &walk::Filter::default())?;
+    let mut flagged = 0;
+
+    for path in &files {
+        let problems = audit(path, checks);
+        if !problems.is_empty() {
+            flagged += 1;
+            println!("{}: {}", path.display(), problems.join("; "));
+        }
+    }
+
+    eprintln!("{flagged} of {} files need attention", files.len());
+    Ok(flagged == 0)
+}
+
+fn audit(path: &Path, checks: Checks) -> Vec<String> {
+    let meta = match Meta::read(path) {
+        Ok(meta) => meta,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let mut problems = Vec::new();
+
+    let required = [
+        ("Title", meta.title.is_some()),
+        ("Artist", meta.artist.is_some()),
+        ("Album", meta.album.is_some()),
+        ("Track", meta.track.is_some()),
+    ];
+    let missing: Vec<_> = required
+        .iter()
+        .filter(|(_, present)| !present)
+        .map(|(name, _)| *name)
+        .collect();
+    if !missing.is_empty() {
+        problems.push(format!("missing {}", missing.join(", ")));
+    }
+
+    if checks.replaygain {
+        let tag = NativeTag::read(path);
+        let has = |name| tag.as_ref().is_some_and(|tag| tag.custom(name).is_some());
+        match (has("REPLAYGAIN_TRACK_GAIN"), has("REPLAYGAIN_ALBUM_GAIN")) {
+            (true, true) => {}
+            (false, false) => problems.push("no ReplayGain tags".into()),
+            (false, true) => problems.push("no ReplayGain track gain".into()),
+            (true, false) => problems.push("no ReplayGain album gain".into()),
+        }
+    }
+
+    problems
+}
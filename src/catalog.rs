@@ -0,0 +1,94 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{json, meta::Meta, Error, Result};
+
+/// Writes the tags of every file that could be read to `path`, so that later runs can plan from
+/// them with `--catalog` instead of reading the files again.
+///
+/// Paths are stored absolute, so the catalog means the same thing wherever it's used from.
+pub fn export(path: &Path, tracks: &[(PathBuf, &Meta)]) -> Result<()> {
+    let mut out = String::from("{\"version\":1,\"tracks\":[");
+    for (idx, (source, meta)) in tracks.iter().enumerate() {
+        if idx > 0 {
+            out.push_str(",\n");
+        }
+        out.push_str("{\"path\":");
+        json::write_str(&mut out, &source.to_string_lossy());
+        let text = [
+            ("album", &meta.album),
+            ("album_artist", &meta.album_artist),
+            ("artist", &meta.artist),
+            ("title", &meta.title),
+            ("work", &meta.work),
+            ("movement", &meta.movement),
+        ];
+        for (key, value) in text {
+            if let Some(value) = value {
+                let _ = write!(out, ",\"{key}\":");
+                json::write_str(&mut out, value);
+            }
+        }
+        let numbers = [
+            ("track", meta.track.map(f64::from)),
+            ("total_tracks", meta.total_tracks.map(f64::from)),
+            ("disc", meta.disc.map(f64::from)),
+            ("total_discs", meta.total_discs.map(f64::from)),
+            ("year", meta.year.map(f64::from)),
+            ("duration", meta.duration),
+            ("movement_number", meta.movement_number.map(f64::from)),
+            ("movement_count", meta.movement_count.map(f64::from)),
+        ];
+        for (key, value) in numbers {
+            if let Some(value) = value {
+                let _ = write!(out, ",\"{key}\":{value}");
+            }
+        }
+        out.push('}');
+    }
+    out.push_str("]}\n");
+    Ok(fs::write(path, out)?)
+}
+
+/// Reads a catalog written by [`export`], in the order its files were found.
+pub fn load(path: &Path) -> Result<Vec<(PathBuf, Meta)>> {
+    let text = fs::read_to_string(path)?;
+    let bad = |message: String| Error::Catalog(format!("{}: {message}", path.display()));
+    let document = json::parse(&text).map_err(bad)?;
+    let tracks = document
+        .get("tracks")
+        .and_then(json::Value::as_array)
+        .ok_or_else(|| bad("no tracks list".into()))?;
+
+    tracks
+        .iter()
+        .map(|track| {
+            let source = track
+                .get("path")
+                .and_then(json::Value::as_str)
+                .ok_or_else(|| bad("track without a path".into()))?;
+            let text = |key| track.get(key).and_then(json::Value::as_str).map(From::from);
+            let number = |key| track.get(key).and_then(json::Value::as_f64);
+            let meta = Meta {
+                album: text("album"),
+                album_artist: text("album_artist"),
+                artist: text("artist"),
+                title: text("title"),
+                track: number("track").map(|n| n as u16),
+                total_tracks: number("total_tracks").map(|n| n as u16),
+                disc: number("disc").map(|n| n as u16),
+                total_discs: number("total_discs").map(|n| n as u16),
+                year: number("year").map(|n| n as i32),
+                duration: number("duration"),
+                work: text("work"),
+                movement: text("movement"),
+                movement_number: number("movement_number").map(|n| n as u16),
+                movement_count: number("movement_count").map(|n| n as u16),
+            };
+            Ok((PathBuf::from(source), meta))
+        })
+        .collect()
+}
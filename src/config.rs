@@ -0,0 +1,465 @@
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{Error, Result};
+
+/// A value in a configuration file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// Settings read from a configuration file.
+///
+/// The format is the commonly used subset of TOML: `[section]` headers, `key = value` pairs, and
+/// comments. Values are strings, integers, booleans, or arrays of them; arrays may span lines.
+/// Keys in sections are looked up as `section.key`.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    values: HashMap<String, Value>,
+}
+
+impl Config {
+    /// Loads the user's configuration file, if there is one.
+    ///
+    /// The file is `$TAGNAME_CONFIG` if set, and otherwise `tagname/config.toml` under the
+    /// platform's configuration directory.
+    pub fn load() -> Result<Self> {
+        let Some(path) = env::var_os("TAGNAME_CONFIG")
+            .map(PathBuf::from)
+            .or_else(default_path)
+        else {
+            return Ok(Self::default());
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text).map_err(|e| match e {
+                Error::Config(message) => Error::Config(format!("{}: {message}", path.display())),
+                e => e,
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut values = HashMap::new();
+        let mut section = String::new();
+        let mut lines = text.lines().enumerate();
+
+        while let Some((idx, line)) = lines.next() {
+            let bad = |message: &str| Error::Config(format!("line {}: {message}", idx + 1));
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[') {
+                let name = name
+                    .strip_suffix(']')
+                    .ok_or_else(|| bad("unclosed section"))?;
+                section = name.trim().to_owned();
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| bad("expected key = value"))?;
+            let key = unquote_key(key.trim());
+            if key.is_empty() {
+                return Err(bad("missing key"));
+            }
+            let mut value = value.trim().to_owned();
+
+            // Arrays may continue over following lines until their closing bracket.
+            if value.starts_with('[') {
+                while !brackets_balanced(&value) {
+                    let (_, next) = lines.next().ok_or_else(|| bad("unclosed array"))?;
+                    value.push(' ');
+                    value.push_str(strip_comment(next).trim());
+                }
+            }
+
+            let (value, rest) = parse_value(&value).map_err(|e| bad(&e))?;
+            if !rest.trim().is_empty() {
+                return Err(bad("unexpected text after value"));
+            }
+
+            let key = if section.is_empty() {
+                key
+            } else {
+                format!("{section}.{key}")
+            };
+            if values.insert(key, value).is_some() {
+                return Err(bad("key set twice"));
+            }
+        }
+
+        Ok(Self { values })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn string(&self, key: &str) -> Result<Option<&str>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(Error::Config(format!("{key}: expected a string"))),
+        }
+    }
+
+    pub fn bool(&self, key: &str) -> Result<bool> {
+        match self.get(key) {
+            None => Ok(false),
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(_) => Err(Error::Config(format!("{key}: expected true or false"))),
+        }
+    }
+
+    /// A list of strings. A single string is accepted as a list of one.
+    pub fn strings(&self, key: &str) -> Result<Vec<&str>> {
+        let bad = || Error::Config(format!("{key}: expected a list of strings"));
+        match self.get(key) {
+            None => Ok(Vec::new()),
+            Some(Value::String(s)) => Ok(vec![s]),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => Ok(s.as_str()),
+                    _ => Err(bad()),
+                })
+                .collect(),
+            Some(_) => Err(bad()),
+        }
+    }
+
+    /// The keys of a section with their string values, as for `[aliases]`.
+    pub fn table(&self, section: &str) -> Result<HashMap<&str, &str>> {
+        let prefix = format!("{section}.");
+        self.values
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(&prefix)?, value)))
+            .map(|(name, value)| match value {
+                Value::String(s) => Ok((name, s.as_str())),
+                _ => Err(Error::Config(format!(
+                    "{section}.{name}: expected a string"
+                ))),
+            })
+            .collect()
+    }
+
+    /// A list of paths, with a leading `~` standing for the home directory.
+    pub fn paths(&self, key: &str) -> Result<Vec<PathBuf>> {
+        Ok(self.strings(key)?.into_iter().map(expand_home).collect())
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    };
+    Some(base?.join("tagname").join("config.toml"))
+}
+
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            Path::new(&home).join(rest.trim_start_matches(['/', '\\']))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Removes a trailing comment, leaving `#` inside strings alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (idx, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..idx],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn brackets_balanced(value: &str) -> bool {
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in value.chars() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            _ => {}
+        }
+        escaped = false;
+    }
+    depth <= 0
+}
+
+fn unquote_key(key: &str) -> String {
+    let quoted = key.len() >= 2
+        && ((key.starts_with('"') && key.ends_with('"'))
+            || (key.starts_with('\'') && key.ends_with('\'')));
+    if quoted {
+        key[1..key.len() - 1].to_owned()
+    } else {
+        key.to_owned()
+    }
+}
+
+/// Parses one value from the start of `s`, returning it and whatever follows.
+fn parse_value(s: &str) -> Result<(Value, &str), String> {
+    let s = s.trim_start();
+    match s.chars().next() {
+        Some('"') => parse_basic_string(&s[1..]).map(|(v, rest)| (Value::String(v), rest)),
+        Some('\'') => {
+            let end = s[1..].find('\'').ok_or("unclosed string")?;
+            Ok((Value::String(s[1..=end].to_owned()), &s[end + 2..]))
+        }
+        Some('[') => {
+            let mut items = Vec::new();
+            let mut rest = s[1..].trim_start();
+            loop {
+                if let Some(after) = rest.strip_prefix(']') {
+                    return Ok((Value::Array(items), after));
+                }
+                let (item, after) = parse_value(rest)?;
+                items.push(item);
+                rest = after.trim_start();
+                if let Some(after) = rest.strip_prefix(',') {
+                    rest = after.trim_start();
+                } else if !rest.starts_with(']') {
+                    return Err("expected , or ] in array".into());
+                }
+            }
+        }
+        Some(_) => {
+            let end = s
+                .find(|c: char| c == ',' || c == ']' || c.is_whitespace())
+                .unwrap_or(s.len());
+            let (word, rest) = s.split_at(end);
+            let value = match word {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                word => Value::Integer(
+                    word.replace('_', "")
+                        .parse()
+                        .map_err(|_| format!("bad value: {word}"))?,
+                ),
+            };
+            Ok((value, rest))
+        }
+        None => Err("missing value".into()),
+    }
+}
+
+fn parse_basic_string(s: &str) -> Result<(String, &str), String> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &s[idx + 1..])),
+            '\\' => match chars.next().map(|(_, c)| c) {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('r') => value.push('\r'),
+                Some('"') => value.push('"'),
+                Some('\\') => value.push('\\'),
+                Some(kind @ ('u' | 'U')) => {
+                    let digits = if kind == 'u' { 4 } else { 8 };
+                    let hex: String = chars.by_ref().take(digits).map(|(_, c)| c).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .filter(|_| hex.len() == digits)
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| format!("bad escape: \\{kind}{hex}"))?;
+                    value.push(c);
+                }
+                Some(c) => return Err(format!("unknown escape: \\{c}")),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    Err("unclosed string".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Value};
+    use crate::Error;
+
+    fn parse(text: &str) -> Config {
+        Config::parse(text).unwrap()
+    }
+
+    fn error(text: &str) -> String {
+        match Config::parse(text) {
+            Err(Error::Config(message)) => message,
+            other => panic!("expected a config error, got {other:?}"),
+        }
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    #[test]
+    fn values_and_sections() {
+        let config = parse(
+            "top = 1\n\
+             [aliases]\n\
+             std = \"%artist - %title\"\n\
+             \"quoted key\" = true\n\
+             [notify]\n\
+             count = 1_000\n\
+             off = false\n",
+        );
+        assert_eq!(config.get("top"), Some(&Value::Integer(1)));
+        assert_eq!(config.get("aliases.std"), Some(&string("%artist - %title")));
+        assert_eq!(config.get("aliases.quoted key"), Some(&Value::Bool(true)));
+        assert_eq!(config.get("notify.count"), Some(&Value::Integer(1000)));
+        assert_eq!(config.get("notify.off"), Some(&Value::Bool(false)));
+        assert_eq!(config.get("std"), None);
+    }
+
+    #[test]
+    fn comments() {
+        let config = parse(
+            "# a whole line\n\
+             \n\
+             a = \"x\" # after a value\n\
+             b = \"# not a comment\"\n\
+             c = 'also # not'\n\
+             d = \"quote \\\" then # inside\" # outside\n",
+        );
+        assert_eq!(config.get("a"), Some(&string("x")));
+        assert_eq!(config.get("b"), Some(&string("# not a comment")));
+        assert_eq!(config.get("c"), Some(&string("also # not")));
+        assert_eq!(config.get("d"), Some(&string("quote \" then # inside")));
+    }
+
+    #[test]
+    fn strings_and_escapes() {
+        let config = parse(
+            r#"
+            basic = "tab\tnew\nline\r \\ \" end"
+            unicode = "caf\u00e9 \U0001F3B5"
+            literal = 'C:\Music\n'
+            empty = ""
+            "#,
+        );
+        assert_eq!(
+            config.get("basic"),
+            Some(&string("tab\tnew\nline\r \\ \" end"))
+        );
+        assert_eq!(config.get("unicode"), Some(&string("caf\u{e9} \u{1f3b5}")));
+        assert_eq!(config.get("literal"), Some(&string("C:\\Music\\n")));
+        assert_eq!(config.get("empty"), Some(&string("")));
+    }
+
+    #[test]
+    fn arrays() {
+        let config = parse(
+            r#"
+            one = ["a", 'b', 3, true]
+            empty = []
+            nested = [["x"], []]
+            lines = [
+                "~/Music",  # a comment
+                "has ] and [ and \" inside",
+                "/srv/music",
+            ]
+            after = 1
+            "#,
+        );
+        assert_eq!(
+            config.get("one"),
+            Some(&Value::Array(vec![
+                string("a"),
+                string("b"),
+                Value::Integer(3),
+                Value::Bool(true),
+            ]))
+        );
+        assert_eq!(config.get("empty"), Some(&Value::Array(Vec::new())));
+        assert_eq!(
+            config.get("nested"),
+            Some(&Value::Array(vec![
+                Value::Array(vec![string("x")]),
+                Value::Array(Vec::new()),
+            ]))
+        );
+        assert_eq!(
+            config.strings("lines").unwrap(),
+            ["~/Music", "has ] and [ and \" inside", "/srv/music"]
+        );
+        assert_eq!(config.get("after"), Some(&Value::Integer(1)));
+    }
+
+    #[test]
+    fn typed_lookups() {
+        let config = parse("one = \"a\"\nmany = [\"a\", \"b\"]\nnumber = 3\n[t]\nk = \"v\"");
+        assert_eq!(config.strings("one").unwrap(), ["a"]);
+        assert_eq!(config.strings("many").unwrap(), ["a", "b"]);
+        assert!(config.strings("missing").unwrap().is_empty());
+        assert!(config.strings("number").is_err());
+        assert!(config.string("number").is_err());
+        assert!(!config.bool("missing").unwrap());
+        assert!(config.bool("one").is_err());
+        assert_eq!(config.table("t").unwrap()["k"], "v");
+    }
+
+    #[test]
+    fn malformed() {
+        let cases = [
+            ("[section", "line 1: unclosed section"),
+            ("just words", "line 1: expected key = value"),
+            ("= 1", "line 1: missing key"),
+            ("a =", "line 1: missing value"),
+            ("a = \"open", "line 1: unclosed string"),
+            ("a = 'open", "line 1: unclosed string"),
+            ("a = \"\\q\"", "line 1: unknown escape: \\q"),
+            ("a = \"\\u12\"", "line 1: bad escape: \\u12\""),
+            ("a = \"\\uD800\"", "line 1: bad escape: \\uD800"),
+            ("a = maybe", "line 1: bad value: maybe"),
+            ("a = 1 2", "line 1: unexpected text after value"),
+            ("a = [1 2]", "line 1: expected , or ] in array"),
+            ("ok = 1\na = [\n  1,\n", "line 2: unclosed array"),
+            ("a = 1\na = 2", "line 2: key set twice"),
+            ("[s]\na = 1\n[s]\na = 2", "line 4: key set twice"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(error(text), expected, "for {text:?}");
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// An index of the files already present in a destination library.
+///
+/// Files are bucketed by size up front; contents are only hashed for buckets that a candidate
+/// actually falls into, so checking a handful of imports against a large library reads little
+/// more than the directory tree.
+#[derive(Debug, Default)]
+pub struct Library {
+    by_size: HashMap<u64, Vec<PathBuf>>,
+    hashes: HashMap<PathBuf, u64>,
+}
+
+impl Library {
+    /// Indexes every file under `root`. A root that doesn't exist yet is simply empty.
+    pub fn scan(root: &Path) -> io::Result<Self> {
+        let mut library = Self::default();
+        if root.is_dir() {
+            library.scan_dir(root)?;
+        }
+        Ok(library)
+    }
+
+    fn scan_dir(&mut self, dir: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let kind = entry.file_type()?;
+            if kind.is_dir() {
+                self.scan_dir(&entry.path())?;
+            } else if kind.is_file() {
+                let size = entry.metadata()?.len();
+                self.by_size.entry(size).or_default().push(entry.path());
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds a file in the library with the same content as `source`, other than `source` itself.
+    pub fn find(&mut self, source: &Path) -> io::Result<Option<PathBuf>> {
+        let size = fs::metadata(source)?.len();
+        let Some(candidates) = self.by_size.get(&size) else {
+            return Ok(None);
+        };
+
+        let source_hash = content_hash(source)?;
+        let canonical_source = fs::canonicalize(source)?;
+        for candidate in candidates {
+            if fs::canonicalize(candidate).is_ok_and(|path| path == canonical_source) {
+                continue;
+            }
+
+            let hash = match self.hashes.get(candidate) {
+                Some(&hash) => hash,
+                None => {
+                    let hash = content_hash(candidate)?;
+                    self.hashes.insert(candidate.clone(), hash);
+                    hash
+                }
+            };
+
+            if hash == source_hash {
+                return Ok(Some(candidate.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Hashes the full contents of a file.
+///
+/// The hash is only compared against others computed in the same run, so the standard library's
+/// hasher is good enough; it is not stable across builds and must not be persisted.
+pub fn content_hash(path: &Path) -> io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let len = file.read(&mut buf)?;
+        if len == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buf[..len]);
+    }
+}
+
+/// True when both files exist and have identical content.
+pub fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(content_hash(a)? == content_hash(b)?)
+}
@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use crate::{audio::Properties, meta::Meta};
+
+/// Prints the tags and audio properties of two files side by side, marking the rows that
+/// differ, to help tell a duplicate from a distinct track with bad tags.
+pub fn print(a: &Path, b: &Path) {
+    let read = |path: &Path| Meta::read(path).map_err(|e| e.to_string());
+    let (left, right) = match (read(a), read(b)) {
+        (Ok(left), Ok(right)) => (left, right),
+        (Err(e), _) => return eprintln!("  {}: {e}", a.display()),
+        (_, Err(e)) => return eprintln!("  {}: {e}", b.display()),
+    };
+    let left_audio = Properties::read(a, Some(&left));
+    let right_audio = Properties::read(b, Some(&right));
+
+    let rows = left.fields().into_iter().zip(right.fields()).chain(
+        audio_fields(&left_audio)
+            .into_iter()
+            .zip(audio_fields(&right_audio)),
+    );
+
+    eprintln!("  a: {}", a.display());
+    eprintln!("  b: {}", b.display());
+    for ((name, left), (_, right)) in rows {
+        let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".into());
+        let marker = if left == right { ' ' } else { '*' };
+        eprintln!("{marker} {name:<12} {:<30} {}", show(&left), show(&right));
+    }
+}
+
+fn audio_fields(properties: &Properties) -> [(&'static str, Option<String>); 5] {
+    [
+        ("Codec", properties.codec.map(From::from)),
+        (
+            "Duration",
+            properties.duration.map(|secs| {
+                let secs = secs.round() as u64;
+                format!("{}:{:02}", secs / 60, secs % 60)
+            }),
+        ),
+        (
+            "Bitrate",
+            properties.bitrate.map(|kbps| format!("{kbps} kbps")),
+        ),
+        (
+            "Sample Rate",
+            properties.sample_rate.map(|hz| format!("{hz} Hz")),
+        ),
+        ("Size", Some(format!("{} bytes", properties.size))),
+    ]
+}
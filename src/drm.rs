@@ -0,0 +1,30 @@
+use std::path::Path;
+
+use crate::{
+    meta,
+    mp4::{audio_children, boxes, movie_box, sample_entries},
+};
+
+/// Extensions of MP4 containers, the only format here that carries DRM.
+const MP4_EXTENSIONS: &[&str] = &["isom", "m4a", "m4b", "m4p", "m4v", "mp4"];
+
+/// True for MP4 files encrypted with FairPlay, as sold by iTunes until 2009.
+///
+/// Protected tracks describe their audio with an encrypted sample entry (`drms`) or one wrapped
+/// in a protection scheme box (`sinf`), which the tag reader rejects with a generic parse error.
+/// Only the sample descriptions are looked at, since tags and lyrics elsewhere in the movie box
+/// can hold any text. Files that can't be read here are assumed unprotected and left for the tag
+/// reader to report.
+pub fn is_protected(path: &Path) -> bool {
+    let is_mp4 = meta::format_extension(path)
+        .is_some_and(|extension| MP4_EXTENSIONS.contains(&extension.as_str()));
+
+    is_mp4
+        && movie_box(path).ok().flatten().is_some_and(|moov| {
+            sample_entries(&moov).into_iter().any(|(kind, body)| {
+                &kind == b"drms"
+                    || audio_children(body)
+                        .is_some_and(|children| boxes(children).any(|(name, _)| &name == b"sinf"))
+            })
+        })
+}
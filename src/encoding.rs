@@ -0,0 +1,107 @@
+use std::fmt;
+
+use crate::meta::Meta;
+
+/// The writing system a track's text tags are in, or a sign that they were decoded wrongly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+    Arabic,
+    Hebrew,
+    /// Chinese, Japanese, or Korean.
+    Cjk,
+    /// Mojibake: text in one encoding read as another, such as `BeyoncÃ©` for `Beyoncé`.
+    Garbled,
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Script::Latin => "Latin script",
+            Script::Cyrillic => "Cyrillic",
+            Script::Greek => "Greek",
+            Script::Arabic => "Arabic",
+            Script::Hebrew => "Hebrew",
+            Script::Cjk => "Chinese, Japanese, or Korean",
+            Script::Garbled => "garbled text",
+        })
+    }
+}
+
+/// Characters Windows-1252 puts where Latin-1 has control codes, which turn up when UTF-8 is
+/// read as Windows-1252.
+const CP1252_EXTRAS: &str = "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ";
+
+/// Works out the script of a track's title, artist, and album, or `None` if they hold no letters.
+pub fn classify(meta: &Meta) -> Option<Script> {
+    let fields = [&meta.title, &meta.artist, &meta.album, &meta.album_artist];
+    let text: String = fields
+        .iter()
+        .filter_map(|field| field.as_deref())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if looks_garbled(&text) {
+        return Some(Script::Garbled);
+    }
+
+    let mut counts = [0usize; 6];
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        let idx = match c as u32 {
+            0x0370..=0x03ff => 2,
+            0x0400..=0x052f => 1,
+            0x0590..=0x05ff => 4,
+            0x0600..=0x06ff | 0x0750..=0x077f => 3,
+            0x1100..=0x11ff
+            | 0x3040..=0x30ff
+            | 0x3130..=0x318f
+            | 0x3400..=0x4dbf
+            | 0x4e00..=0x9fff
+            | 0xac00..=0xd7af => 5,
+            _ => 0,
+        };
+        counts[idx] += 1;
+    }
+
+    let (idx, &count) = counts.iter().enumerate().max_by_key(|(_, &count)| count)?;
+    (count > 0).then_some(
+        [
+            Script::Latin,
+            Script::Cyrillic,
+            Script::Greek,
+            Script::Arabic,
+            Script::Hebrew,
+            Script::Cjk,
+        ][idx],
+    )
+}
+
+/// True for the telltale signs of mojibake: replacement characters, stray control codes, UTF-8
+/// lead bytes read as Latin-1 (`Ã©`), or text made mostly of Latin-1 accented letters, which is
+/// what Cyrillic or Greek in their old single-byte encodings looks like read as Latin-1.
+fn looks_garbled(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    if chars
+        .iter()
+        .any(|&c| c == '\u{fffd}' || ('\u{80}'..='\u{9f}').contains(&c))
+    {
+        return true;
+    }
+
+    let utf8_as_latin1 = chars.windows(2).any(|pair| {
+        matches!(pair[0], 'Ã' | 'Â' | 'â' | 'Ð' | 'Ñ')
+            && (('\u{a0}'..='\u{bf}').contains(&pair[1]) || CP1252_EXTRAS.contains(pair[1]))
+    });
+    if utf8_as_latin1 {
+        return true;
+    }
+
+    let letters = chars.iter().filter(|c| c.is_alphabetic()).count();
+    let accented = chars
+        .iter()
+        .filter(|&&c| ('\u{c0}'..='\u{ff}').contains(&c) && c != '×' && c != '÷')
+        .count();
+    letters >= 4 && accented * 2 > letters
+}
@@ -0,0 +1,576 @@
+use std::{borrow::Cow, collections::HashMap, fmt, str::FromStr};
+
+use regex::Regex;
+
+use crate::{album::Album, meta::Meta, profile::FsProfile, Error, Result};
+
+/// What `%groupartist` calls an album whose tracks disagree about the artist.
+const VARIOUS_ARTISTS: &str = "Various Artists";
+
+#[derive(Debug, Clone, Copy)]
+pub enum Tag {
+    Album,
+    AlbumArtist,
+    AlbumDuration,
+    AlbumTracks,
+    Artist,
+    Decade,
+    Disc,
+    GroupArtist,
+    Initial,
+    Movement,
+    MovementNumber,
+    NumberedMovement,
+    Title,
+    Track,
+    Work,
+    Year,
+}
+
+impl Tag {
+    fn read_from<'a>(self, meta: &'a Meta, album: &'a Album) -> Result<Value<'a>> {
+        let missing = || Error::MissingTag(self);
+        let text = |value: &'a Option<String>| {
+            value
+                .as_deref()
+                .map(|value| Value::Text(value.into()))
+                .ok_or_else(missing)
+        };
+
+        match self {
+            Tag::Album => text(&meta.album),
+            Tag::AlbumArtist if album.compilation && album.album_artist.is_none() => {
+                Err(Error::NoAlbumArtist)
+            }
+            Tag::AlbumArtist => text(&album.album_artist),
+            Tag::AlbumDuration => {
+                let seconds = album.duration.ok_or_else(missing)?;
+                Ok(Value::number((seconds / 60.0).round() as i64))
+            }
+            Tag::AlbumTracks => Ok(Value::number(album.track_count as i64)),
+            Tag::Artist => text(&meta.artist),
+            Tag::Decade => Ok(Value::Decade(
+                i64::from(meta.year.ok_or_else(missing)?).div_euclid(10) * 10,
+            )),
+            Tag::Disc => match meta.disc {
+                Some(disc) => Ok(Value::number(disc)),
+                // An untagged track of a single-disc album can only be on disc one.
+                None if album.disc_count == 1 => Ok(Value::number(1)),
+                None => Err(missing()),
+            },
+            Tag::GroupArtist => match &album.album_artist {
+                Some(artist) => Ok(Value::Text(artist.into())),
+                None if album.compilation => Ok(Value::Text(VARIOUS_ARTISTS.into())),
+                None => Err(missing()),
+            },
+            Tag::Initial => {
+                let artist = meta.artist.as_deref().ok_or_else(missing)?;
+                Ok(Value::Text(initial(&sort_name(artist)).into()))
+            }
+            Tag::Movement => text(&meta.movement),
+            Tag::MovementNumber => {
+                let (value, width) = movement_number(meta, album)?;
+                Ok(Value::Number { value, width })
+            }
+            Tag::NumberedMovement => {
+                let (value, width) = movement_number(meta, album)?;
+                let movement = meta
+                    .movement
+                    .as_deref()
+                    .ok_or(Error::MissingTag(Tag::Movement))?;
+                Ok(Value::Text(format!("{value:0width$}. {movement}").into()))
+            }
+            Tag::Title => text(&meta.title),
+            Tag::Track => Ok(Value::number(meta.track.ok_or_else(missing)?)),
+            Tag::Work => text(&meta.work),
+            Tag::Year => Ok(Value::number(meta.year.ok_or_else(missing)?)),
+        }
+    }
+}
+
+impl Tag {
+    /// The modifiers that mean something for this key.
+    fn accepts(self, modifier: Modifier) -> bool {
+        match self {
+            Tag::Album
+            | Tag::AlbumArtist
+            | Tag::Artist
+            | Tag::GroupArtist
+            | Tag::Movement
+            | Tag::Title
+            | Tag::Work => matches!(modifier, Modifier::The),
+            Tag::Track if matches!(modifier, Modifier::Auto) => true,
+            Tag::AlbumDuration
+            | Tag::AlbumTracks
+            | Tag::Disc
+            | Tag::MovementNumber
+            | Tag::Track
+            | Tag::Year => {
+                matches!(
+                    modifier,
+                    Modifier::Pad(_) | Modifier::Roman | Modifier::Ordinal | Modifier::Bucket(_)
+                )
+            }
+            Tag::Decade => matches!(modifier, Modifier::Short),
+            // Already a single letter of the sort name.
+            Tag::Initial => false,
+            Tag::NumberedMovement => false,
+        }
+    }
+
+    /// How to write the modifiers this key accepts, for error messages.
+    fn accepted(self) -> &'static str {
+        match self {
+            Tag::Album
+            | Tag::AlbumArtist
+            | Tag::Artist
+            | Tag::GroupArtist
+            | Tag::Movement
+            | Tag::Title
+            | Tag::Work => "only :the",
+            Tag::Track => "a width such as :02 or :auto, :roman, :ordinal, or :bucket(n)",
+            Tag::AlbumDuration | Tag::AlbumTracks | Tag::Disc | Tag::MovementNumber | Tag::Year => {
+                "a width such as :02, :roman, :ordinal, or :bucket(n)"
+            }
+            Tag::Decade => "only :short",
+            Tag::Initial | Tag::NumberedMovement => "no modifiers",
+        }
+    }
+}
+
+impl FromStr for Tag {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim_start_matches('%');
+        match s {
+            "album" => Ok(Tag::Album),
+            "albumartist" => Ok(Tag::AlbumArtist),
+            "albumduration" => Ok(Tag::AlbumDuration),
+            "albumtracks" => Ok(Tag::AlbumTracks),
+            "artist" => Ok(Tag::Artist),
+            "decade" => Ok(Tag::Decade),
+            "disc" => Ok(Tag::Disc),
+            "groupartist" => Ok(Tag::GroupArtist),
+            "initial" => Ok(Tag::Initial),
+            "movement" => Ok(Tag::Movement),
+            "movementnumber" => Ok(Tag::MovementNumber),
+            "numberedmovement" => Ok(Tag::NumberedMovement),
+            "title" => Ok(Tag::Title),
+            "track" => Ok(Tag::Track),
+            "work" => Ok(Tag::Work),
+            "year" => Ok(Tag::Year),
+            _ => Err(Error::Format(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tag::Album => f.write_str("Album"),
+            Tag::AlbumArtist => f.write_str("Album Artist"),
+            Tag::AlbumDuration => f.write_str("Duration"),
+            Tag::AlbumTracks => f.write_str("Track Count"),
+            Tag::Artist => f.write_str("Artist"),
+            Tag::Decade => f.write_str("Year"),
+            Tag::Disc => f.write_str("Disc"),
+            Tag::GroupArtist => f.write_str("Album Artist"),
+            Tag::Initial => f.write_str("Artist"),
+            Tag::Movement => f.write_str("Movement"),
+            Tag::MovementNumber => f.write_str("Movement Number"),
+            Tag::NumberedMovement => f.write_str("Movement Number"),
+            Tag::Title => f.write_str("Title"),
+            Tag::Track => f.write_str("Track"),
+            Tag::Work => f.write_str("Work"),
+            Tag::Year => f.write_str("Year"),
+        }
+    }
+}
+
+/// A tag's value, before formatting.
+#[derive(Debug, Clone)]
+enum Value<'a> {
+    Text(Cow<'a, str>),
+
+    /// A number, zero-padded to `width` digits unless a modifier says otherwise.
+    Number {
+        value: i64,
+        width: usize,
+    },
+
+    /// The first year of a decade, printed as `1990s`.
+    Decade(i64),
+}
+
+impl<'a> Value<'a> {
+    fn number(value: impl Into<i64>) -> Self {
+        Value::Number {
+            value: value.into(),
+            width: 0,
+        }
+    }
+
+    fn render(self, modifier: Option<Modifier>) -> Cow<'a, str> {
+        match (self, modifier) {
+            (Value::Text(text), Some(Modifier::The)) => sort_name(&text).into_owned().into(),
+            (Value::Text(text), _) => text,
+            (Value::Number { value, width }, None) => format!("{value:0width$}").into(),
+            (Value::Number { value, width }, Some(modifier)) => modifier.apply(value, width).into(),
+            (Value::Decade(year), Some(Modifier::Short)) => {
+                format!("{:02}s", year.rem_euclid(100)).into()
+            }
+            (Value::Decade(year), _) => format!("{year}s").into(),
+        }
+    }
+}
+
+/// A formatting option applied to a key, written after a colon: `%track:02`.
+///
+/// Numeric modifiers go with numeric keys and text modifiers with text keys; decades take only
+/// `:short`. Templates that pair a key with a modifier it can't use are rejected when parsed.
+#[derive(Debug, Clone, Copy)]
+enum Modifier {
+    /// Zero-pad to this many digits.
+    Pad(usize),
+    /// Zero-pad to as many digits as the album's highest track number needs: `%track:auto`.
+    Auto,
+    /// Upper-case roman numerals: `II`.
+    Roman,
+    /// English ordinals: `3rd`.
+    Ordinal,
+    /// Sort form of a name, with a leading article moved to the end: `Beatles, The`.
+    The,
+    /// Two-digit decades: `90s`.
+    Short,
+    /// The range of this many values the number falls in: `1990-1994` for `%year:bucket(5)`.
+    Bucket(i64),
+}
+
+impl Modifier {
+    /// Settles a modifier that depends on the album, such as `:auto`, for one of its tracks.
+    fn fit(self, album: &Album) -> Self {
+        match self {
+            Modifier::Auto => Modifier::Pad(album.track_width),
+            modifier => modifier,
+        }
+    }
+
+    /// Formats a number whose default width is `width`.
+    fn apply(self, value: i64, width: usize) -> String {
+        match self {
+            Modifier::Pad(width) => format!("{value:0width$}"),
+            // Settled by `fit` before formatting.
+            Modifier::Auto => value.to_string(),
+            Modifier::Roman => roman(value).unwrap_or_else(|| value.to_string()),
+            Modifier::Ordinal => ordinal(value),
+            Modifier::Bucket(size) => {
+                let start = value.div_euclid(size) * size;
+                format!("{start:0width$}-{:0width$}", start.saturating_add(size - 1))
+            }
+            Modifier::The | Modifier::Short => format!("{value:0width$}"),
+        }
+    }
+}
+
+impl FromStr for Modifier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "roman" => Ok(Modifier::Roman),
+            "ordinal" => Ok(Modifier::Ordinal),
+            "the" => Ok(Modifier::The),
+            "short" => Ok(Modifier::Short),
+            "auto" => Ok(Modifier::Auto),
+            s if s.starts_with("bucket(") && s.ends_with(')') => {
+                match s["bucket(".len()..s.len() - 1].parse() {
+                    Ok(size) if size > 0 => Ok(Modifier::Bucket(size)),
+                    _ => Err(Error::Modifier(format!(
+                        "{s} (buckets hold at least one value)"
+                    ))),
+                }
+            }
+            s if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => Ok(Modifier::Pad(
+                s.parse().map_err(|_| Error::Modifier(s.into()))?,
+            )),
+            _ => Err(Error::Modifier(s.into())),
+        }
+    }
+}
+
+/// Roman numerals for 1 through 3999, the range the notation covers without extensions.
+fn roman(value: i64) -> Option<String> {
+    const NUMERALS: &[(i64, &str)] = &[
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+
+    if !(1..4000).contains(&value) {
+        return None;
+    }
+
+    let mut remaining = value;
+    let mut s = String::new();
+    for &(step, numeral) in NUMERALS {
+        while remaining >= step {
+            s += numeral;
+            remaining -= step;
+        }
+    }
+    Some(s)
+}
+
+fn ordinal(value: i64) -> String {
+    let suffix = match (value % 10, value % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{value}{suffix}")
+}
+
+/// A track's movement number and the width its work's numbers are printed at. Numbering the
+/// rest of the work contradicts is refused rather than printed.
+fn movement_number(meta: &Meta, album: &Album) -> Result<(i64, usize)> {
+    let number = meta
+        .movement_number
+        .ok_or(Error::MissingTag(Tag::MovementNumber))?;
+    let work = &album.works[&meta.work];
+    if let Some(problem) = &work.problem {
+        let name = meta.work.as_deref().unwrap_or("untitled work");
+        return Err(Error::Movements(format!("{name}: {problem}")));
+    }
+    Ok((number.into(), work.width))
+}
+
+/// The sort form of a name: "The Beatles" sorts as "Beatles, The".
+fn sort_name(name: &str) -> Cow<'_, str> {
+    match name.get(..4) {
+        Some(article) if article.eq_ignore_ascii_case("the ") && name.len() > 4 => {
+            format!("{}, {}", &name[4..], &name[..3]).into()
+        }
+        _ => name.into(),
+    }
+}
+
+/// The upper-case first letter of a name, or `#` for names starting with anything else.
+fn initial(name: &str) -> String {
+    match name.chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_uppercase().collect(),
+        _ => "#".into(),
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Element {
+    Tag {
+        tag: Tag,
+        modifier: Option<Modifier>,
+
+        /// Written `%year?`: when the tag is missing, drop it and the literal text that goes with
+        /// it instead of failing.
+        optional: bool,
+    },
+    Literal(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Format {
+    elements: Vec<Element>,
+    profile: FsProfile,
+}
+
+impl Format {
+    /// Replaces user-defined keys in a template with what they stand for, so that with `n` set to
+    /// `%track:02`, `%n - %title` reads `%track:02 - %title`.
+    ///
+    /// An alias may be given a modifier or `?` of its own where it's used, as long as it stands
+    /// for a single plain key. Aliases can't redefine built-in keys or refer to other aliases.
+    pub fn expand_aliases(template: &str, aliases: &HashMap<&str, &str>) -> Result<String> {
+        for &name in aliases.keys() {
+            if name.is_empty() || !name.bytes().all(|b| b.is_ascii_lowercase()) {
+                return Err(Error::Config(format!(
+                    "aliases.{name}: names are lower-case letters only"
+                )));
+            }
+            if name.parse::<Tag>().is_ok() {
+                return Err(Error::Config(format!(
+                    "aliases.{name}: %{name} is already a key"
+                )));
+            }
+        }
+
+        let rx = Regex::new(r"%([a-z]+)(:[a-z0-9]+(?:\(\d+\))?)?(\?)?").unwrap();
+        let plain_key = Regex::new(r"^%[a-z]+$").unwrap();
+        let single_key = Regex::new(r"^%[a-z]+(?::[a-z0-9]+(?:\(\d+\))?)?\??$").unwrap();
+        let mut expanded = String::with_capacity(template.len());
+        let mut last = 0;
+        for cx in rx.captures_iter(template) {
+            let Some(&value) = aliases.get(cx.get(1).unwrap().as_str()) else {
+                continue;
+            };
+            let whole = cx.get(0).unwrap();
+            expanded += &template[last..whole.start()];
+            expanded += value;
+            if let Some(modifier) = cx.get(2) {
+                if !plain_key.is_match(value) {
+                    return Err(Error::Modifier(format!(
+                        "{} (%{} stands for {value}, which can't take another modifier)",
+                        &modifier.as_str()[1..],
+                        cx.get(1).unwrap().as_str()
+                    )));
+                }
+                expanded += modifier.as_str();
+            }
+            // Made optional, an alias for several keys would drop only its last one.
+            if cx.get(3).is_some() {
+                if !single_key.is_match(value) {
+                    return Err(Error::Modifier(format!(
+                        "? (%{} stands for {value}, which can't be made optional as a whole)",
+                        cx.get(1).unwrap().as_str()
+                    )));
+                }
+                if !value.ends_with('?') {
+                    expanded.push('?');
+                }
+            }
+            last = whole.end();
+        }
+        expanded += &template[last..];
+        Ok(expanded)
+    }
+
+    pub fn from_template(template: &str) -> Result<Self> {
+        let rx = Regex::new(r#"(%[a-z]+)(?::([a-z0-9]+(?:\(\d+\))?))?(\?)?|([^%]+)"#).unwrap();
+        let elements: Result<Vec<_>> = rx
+            .captures_iter(template)
+            .map(|cx| {
+                if let Some(key) = cx.get(1) {
+                    let tag: Tag = key.as_str().parse()?;
+                    let modifier = cx.get(2).map(|m| m.as_str().parse()).transpose()?;
+                    if let Some(false) = modifier.map(|modifier| tag.accepts(modifier)) {
+                        return Err(Error::Modifier(format!(
+                            "{} doesn't apply to {}, which takes {}",
+                            cx.get(2).unwrap().as_str(),
+                            key.as_str(),
+                            tag.accepted()
+                        )));
+                    }
+                    Ok(Element::Tag {
+                        tag,
+                        modifier,
+                        optional: cx.get(3).is_some(),
+                    })
+                } else {
+                    Ok(Element::Literal(cx.get(4).unwrap().as_str().into()))
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            elements: elements?,
+            profile: FsProfile::default(),
+        })
+    }
+
+    /// Cleans tag values and names for the given filesystem.
+    pub fn with_profile(self, profile: FsProfile) -> Self {
+        Self { profile, ..self }
+    }
+
+    pub fn build_name(&self, meta: &Meta, album: &Album) -> Result<String> {
+        // Each piece is the rendered text, or `None` for an optional tag that's missing.
+        let mut pieces = Vec::with_capacity(self.elements.len());
+        for element in &self.elements {
+            pieces.push(match element {
+                Element::Tag {
+                    tag,
+                    modifier,
+                    optional,
+                } => match tag.read_from(meta, album) {
+                    Ok(value) => Some(
+                        self.profile
+                            .clean_value(&value.render(modifier.map(|m| m.fit(album))))
+                            .into_owned(),
+                    ),
+                    Err(Error::MissingTag(_) | Error::NoAlbumArtist) if *optional => None,
+                    Err(e) => return Err(e),
+                },
+                Element::Literal(lit) => Some(lit.clone()),
+            });
+        }
+
+        for idx in 0..pieces.len() {
+            if pieces[idx].is_none() {
+                drop_adjacent_literal(&self.elements, &mut pieces, idx);
+            }
+        }
+
+        Ok(self
+            .profile
+            .clean_name(pieces.into_iter().flatten().collect()))
+    }
+}
+
+/// Removes the literal text that belongs to the missing optional tag at `idx`.
+///
+/// That's the text leading up to the tag within the same path component, so `%title - %year?`
+/// loses its ` - `. A tag at the start of a component takes the text after it instead, as in
+/// `%year? - %title`, and a tag that is a whole component takes its separator. When the dropped
+/// text ends in an opening bracket, the matching closing bracket after the tag goes too, so
+/// `%album (%year?)` becomes just the album.
+fn drop_adjacent_literal(elements: &[Element], pieces: &mut [Option<String>], idx: usize) {
+    let is_literal = |idx: usize| matches!(elements.get(idx), Some(Element::Literal(_)));
+
+    if idx > 0 && is_literal(idx - 1) {
+        if let Some(before) = pieces[idx - 1].as_mut() {
+            let keep = before.rfind('/').map_or(0, |slash| slash + 1);
+            if keep < before.len() {
+                let opener = before.chars().next_back();
+                before.truncate(keep);
+
+                let closer = match opener {
+                    Some('(') => Some(')'),
+                    Some('[') => Some(']'),
+                    Some('{') => Some('}'),
+                    _ => None,
+                };
+                if let (Some(closer), true) = (closer, is_literal(idx + 1)) {
+                    if let Some(after) = pieces[idx + 1].as_mut() {
+                        if after.starts_with(closer) {
+                            after.remove(0);
+                        }
+                    }
+                }
+                return;
+            }
+        }
+    }
+
+    if is_literal(idx + 1) {
+        if let Some(after) = pieces[idx + 1].as_mut() {
+            // A tag that makes up a whole component takes its separator along, rather than
+            // leaving an empty directory name behind.
+            let end = match after.find('/') {
+                Some(0) => 1,
+                Some(slash) => slash,
+                None => after.len(),
+            };
+            after.replace_range(..end, "");
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+/// The name of the files that list paths to skip during traversal.
+pub const FILE_NAME: &str = ".tagnameignore";
+
+/// One line of an ignore file.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// The directory holding the ignore file; patterns match paths relative to it.
+    base: PathBuf,
+    rx: Regex,
+    /// A `!pattern` that brings back something an earlier rule ignored.
+    negate: bool,
+    /// A `pattern/` that only matches directories.
+    dir_only: bool,
+}
+
+/// Reads the ignore file in `dir`, if there is one.
+///
+/// The syntax is gitignore's: one glob per line, `#` comments, `!` to negate, a trailing `/`
+/// for directories only, and a `/` anywhere else to anchor the pattern to this directory rather
+/// than matching at any depth. `*` and `?` stop at separators; `**` doesn't.
+pub fn read(dir: &Path) -> io::Result<Vec<Rule>> {
+    let text = match fs::read_to_string(dir.join(FILE_NAME)) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut rules = Vec::new();
+    for line in text.lines() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (negate, pattern) = match line.strip_prefix('!') {
+            Some(pattern) => (true, pattern),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, pattern) = match pattern.strip_suffix('/') {
+            Some(pattern) => (true, pattern),
+            None => (false, pattern),
+        };
+        let anchored = pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+
+        let prefix = if anchored { "^" } else { "^(?:.*/)?" };
+        let Ok(rx) = Regex::new(&format!("{prefix}{}$", glob_to_regex(pattern))) else {
+            // A malformed pattern matches nothing, as in git.
+            continue;
+        };
+        rules.push(Rule {
+            base: dir.to_owned(),
+            rx,
+            negate,
+            dir_only,
+        });
+    }
+    Ok(rules)
+}
+
+/// True when the last rule matching `path` ignores it.
+pub fn is_ignored(rules: &[Rule], path: &Path, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| {
+            let Ok(relative) = path.strip_prefix(&rule.base) else {
+                return false;
+            };
+            let relative: Vec<_> = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect();
+            (is_dir || !rule.dir_only) && rule.rx.is_match(&relative.join("/"))
+        })
+        .is_some_and(|rule| !rule.negate)
+}
+
+/// Translates a gitignore-style glob into a regular expression, without anchors.
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut rx = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    rx += "(?:.*/)?";
+                } else {
+                    rx += ".*";
+                }
+            }
+            '*' => rx += "[^/]*",
+            '?' => rx += "[^/]",
+            '[' => {
+                let mut class = String::from("[");
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    class.push('^');
+                }
+                let mut closed = false;
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        closed = true;
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        class.push('\\');
+                    }
+                    class.push(c);
+                }
+                if closed {
+                    rx += &class;
+                    rx.push(']');
+                } else {
+                    rx += &regex::escape(&class);
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    rx += &regex::escape(&c.to_string());
+                }
+            }
+            c => rx += &regex::escape(&c.to_string()),
+        }
+    }
+    rx
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    diff, json,
+    plan::{free_name, Entry, Mode, Status},
+};
+
+/// Asks about each planned change before anything is done.
+///
+/// Ordinary changes get a yes or no. Collisions get choices that fit them: a file already at the
+/// target can be overwritten, and either kind can be kept under a numbered name, skipped, or
+/// compared tag by tag first. Quitting skips everything not yet confirmed.
+///
+/// With a session file, every answer is saved as it's given, and answers saved by earlier runs
+/// are reused for files whose planned change hasn't changed since, so a long review can be
+/// picked up where it was left.
+pub fn review(entries: &mut [Entry], mode: &Mode, session: Option<&Path>) -> io::Result<()> {
+    let mut stdin = io::stdin().lock();
+    let mut accept_all = false;
+    let mut session = session.map(Session::open).transpose()?;
+    if let Some(session) = &session {
+        let restored = entries
+            .iter()
+            .filter(|entry| session.answer(entry).is_some())
+            .count();
+        if restored > 0 {
+            eprintln!("reusing {restored} answers from the session file");
+        }
+    }
+
+    for idx in 0..entries.len() {
+        let entry = &entries[idx];
+
+        // An earlier answer may have settled a collision this file was part of.
+        if entry.status == Status::Conflict && claimants(entries, idx).is_empty() {
+            let target = entry.target.as_deref().unwrap();
+            entries[idx].status = if target.exists() {
+                Status::SkippedExisting
+            } else {
+                mode.change_status()
+            };
+        }
+
+        let entry = &entries[idx];
+        let source = entry.source.display();
+        let target = entry.target.clone().unwrap_or_default();
+        let saved = session.as_ref().and_then(|session| session.answer(entry));
+        let decision = match entry.status {
+            _ if saved.is_some() => saved.unwrap(),
+            status if status.is_change() || status == Status::Hardlinked => {
+                if accept_all {
+                    if let Some(session) = &mut session {
+                        session.record(entry, Choice::Yes)?;
+                    }
+                    continue;
+                }
+                let question = match status {
+                    Status::Hardlinked => format!(
+                        "{source}: replace with a hard link to {}?",
+                        entry.duplicate.as_deref().unwrap().display()
+                    ),
+                    _ => format!("{source} -> {}?", target.display()),
+                };
+                ask(&mut stdin, &question, &[YES, NO, ALL, QUIT])?
+            }
+            Status::SkippedExisting => {
+                let question = format!("{source} -> {}: already exists.", target.display());
+                loop {
+                    match ask(
+                        &mut stdin,
+                        &question,
+                        &[OVERWRITE, SUFFIX, SKIP, DIFF, QUIT],
+                    )? {
+                        Choice::Diff => diff::print(&entry.source, &target),
+                        choice => break choice,
+                    }
+                }
+            }
+            Status::Conflict => {
+                let other = entries[claimants(entries, idx)[0]].source.clone();
+                let question = format!(
+                    "{source} -> {}: also wanted by {}.",
+                    target.display(),
+                    other.display()
+                );
+                loop {
+                    match ask(&mut stdin, &question, &[SUFFIX, SKIP, DIFF, QUIT])? {
+                        Choice::Diff => diff::print(&entry.source, &other),
+                        choice => break choice,
+                    }
+                }
+            }
+            _ => continue,
+        };
+        if let (Some(session), None) = (&mut session, saved) {
+            session.record(entry, decision)?;
+        }
+
+        match decision {
+            Choice::Yes => {}
+            Choice::All => accept_all = true,
+            Choice::No | Choice::Skip => entries[idx].status = Status::SkippedByUser,
+            Choice::Overwrite => entries[idx].status = mode.change_status(),
+            Choice::Suffix => {
+                let free = free_name(entries, &target, true);
+                eprintln!("  as {}", free.display());
+                entries[idx].target = Some(free);
+                entries[idx].status = mode.change_status();
+            }
+            Choice::Quit => {
+                for entry in &mut entries[idx..] {
+                    let pending = entry.status.is_change()
+                        || matches!(
+                            entry.status,
+                            Status::Hardlinked | Status::Conflict | Status::SkippedExisting
+                        );
+                    if pending {
+                        entry.status = Status::SkippedByUser;
+                    }
+                }
+                break;
+            }
+            Choice::Diff => unreachable!(),
+        }
+    }
+    Ok(())
+}
+
+/// Answers saved from interactive reviews, one JSON object per line.
+///
+/// An answer belongs to a source and the change proposed for it, so it stops applying once a
+/// different template or collision gives the file a different proposal.
+struct Session {
+    file: File,
+    answers: HashMap<(PathBuf, PathBuf), Choice>,
+}
+
+impl Session {
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut answers = HashMap::new();
+        let text = match fs::read_to_string(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            text => text?,
+        };
+        for line in text.lines() {
+            // A line cut short by a crash is only a lost answer.
+            let Ok(answer) = json::parse(line) else {
+                continue;
+            };
+            let field = |key| answer.get(key).and_then(json::Value::as_str);
+            let (Some(source), Some(target), Some(choice)) =
+                (field("source"), field("target"), field("choice"))
+            else {
+                continue;
+            };
+            if let Some(choice) = Choice::from_name(choice) {
+                answers.insert((source.into(), target.into()), choice);
+            }
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file, answers })
+    }
+
+    /// The path an entry's proposal is known by: its target, or for a hard link, its duplicate.
+    fn proposal(entry: &Entry) -> Option<&Path> {
+        match entry.status {
+            Status::Hardlinked => entry.duplicate.as_deref(),
+            _ => entry.target.as_deref(),
+        }
+    }
+
+    fn answer(&self, entry: &Entry) -> Option<Choice> {
+        let key = (entry.source.clone(), Self::proposal(entry)?.to_owned());
+        self.answers
+            .get(&key)
+            .copied()
+            .filter(|choice| choice.fits(entry.status))
+    }
+
+    fn record(&mut self, entry: &Entry, choice: Choice) -> io::Result<()> {
+        let (Some(name), Some(target)) = (choice.name(), Self::proposal(entry)) else {
+            return Ok(());
+        };
+        let mut line = String::from("{\"source\":");
+        json::write_str(&mut line, &entry.source.to_string_lossy());
+        line.push_str(",\"target\":");
+        json::write_str(&mut line, &target.to_string_lossy());
+        line.push_str(",\"choice\":");
+        json::write_str(&mut line, name);
+        line.push_str("}\n");
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Choice {
+    Yes,
+    No,
+    All,
+    Overwrite,
+    Suffix,
+    Skip,
+    Diff,
+    Quit,
+}
+
+impl Choice {
+    /// How a choice is saved in a session file. Choices that aren't about one file aren't saved;
+    /// accepting all is saved as a yes for each file it covers.
+    fn name(self) -> Option<&'static str> {
+        match self {
+            Choice::Yes => Some("yes"),
+            Choice::No => Some("no"),
+            Choice::Overwrite => Some("overwrite"),
+            Choice::Suffix => Some("suffix"),
+            Choice::Skip => Some("skip"),
+            Choice::All | Choice::Diff | Choice::Quit => None,
+        }
+    }
+
+    /// Whether this is one of the choices offered for an entry with `status`.
+    fn fits(self, status: Status) -> bool {
+        match status {
+            status if status.is_change() || status == Status::Hardlinked => {
+                matches!(self, Choice::Yes | Choice::No)
+            }
+            Status::SkippedExisting => {
+                matches!(self, Choice::Overwrite | Choice::Suffix | Choice::Skip)
+            }
+            Status::Conflict => matches!(self, Choice::Suffix | Choice::Skip),
+            _ => false,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            Choice::Yes,
+            Choice::No,
+            Choice::Overwrite,
+            Choice::Suffix,
+            Choice::Skip,
+        ]
+        .into_iter()
+        .find(|choice| choice.name() == Some(name))
+    }
+}
+
+/// A choice, its key, and how it's offered.
+type Offer = (Choice, char, &'static str);
+
+const YES: Offer = (Choice::Yes, 'y', "[y]es");
+const NO: Offer = (Choice::No, 'n', "[n]o");
+const ALL: Offer = (Choice::All, 'a', "[a]ll");
+const OVERWRITE: Offer = (Choice::Overwrite, 'o', "[o]verwrite");
+const SUFFIX: Offer = (Choice::Suffix, 's', "keep both with a [s]uffix");
+const SKIP: Offer = (Choice::Skip, 'k', "s[k]ip");
+const DIFF: Offer = (Choice::Diff, 'd', "show tag [d]iff");
+const QUIT: Offer = (Choice::Quit, 'q', "[q]uit");
+
+/// Asks until one of `choices` is picked. The end of input counts as quitting.
+fn ask(input: &mut impl BufRead, question: &str, choices: &[Offer]) -> io::Result<Choice> {
+    let menu: Vec<_> = choices.iter().map(|(_, _, label)| *label).collect();
+    loop {
+        eprint!("{question} {} ", menu.join(", "));
+        io::stderr().flush()?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            eprintln!();
+            return Ok(Choice::Quit);
+        }
+        let answer = answer.trim().to_ascii_lowercase();
+        let picked = choices
+            .iter()
+            .find(|(_, key, _)| answer.len() == 1 && answer.starts_with(*key));
+        if let Some((choice, _, _)) = picked {
+            return Ok(*choice);
+        }
+    }
+}
+
+/// Other entries still competing for the target of the entry at `idx`.
+fn claimants(entries: &[Entry], idx: usize) -> Vec<usize> {
+    let target = entries[idx].target.as_deref();
+    (0..entries.len())
+        .filter(|&other| other != idx)
+        .filter(|&other| {
+            let entry = &entries[other];
+            (entry.status.is_change() || entry.status == Status::Conflict)
+                && entry.target.as_deref() == target
+        })
+        .collect()
+}
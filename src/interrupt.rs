@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Catches Ctrl-C while files are being changed, so that an interrupted run stops between
+/// operations rather than in the middle of one, and still records what it did.
+///
+/// A second Ctrl-C exits at once. The default handling comes back when the guard is dropped.
+pub struct Guard(());
+
+impl Guard {
+    pub fn install() -> Self {
+        // SAFETY: the handler only touches an atomic and calls `_exit`, both of which are
+        // async-signal-safe.
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                handle as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+        Guard(())
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // SAFETY: restores the default disposition.
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+}
+
+/// True once Ctrl-C has been pressed under a guard.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+extern "C" fn handle(_: libc::c_int) {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        // SAFETY: `_exit` ends the process without running anything that isn't signal-safe.
+        unsafe { libc::_exit(130) }
+    }
+}
@@ -0,0 +1,421 @@
+use std::fmt::Write;
+
+/// Appends `s` to `out` as a quoted JSON string.
+pub fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// A parsed JSON document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The member `key` of an object.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a complete JSON document.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(parser.error("unexpected text after document"));
+    }
+    Ok(value)
+}
+
+/// How deeply arrays and objects may nest. Nothing tagname writes comes close, and parsing is
+/// recursive, so a limit keeps a corrupt file from overflowing the stack.
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("{message} at byte {}", self.pos)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, literal: &str) -> Result<(), String> {
+        if self.text[self.pos..].starts_with(literal) {
+            self.pos += literal.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {literal}")))
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        if matches!(self.peek(), Some(b'[' | b'{')) {
+            if self.depth == MAX_DEPTH {
+                return Err(self.error("nested too deeply"));
+            }
+            self.depth += 1;
+            let value = self.container();
+            self.depth -= 1;
+            return value;
+        }
+        match self.peek() {
+            Some(b'n') => self.expect("null").map(|_| Value::Null),
+            Some(b't') => self.expect("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                while matches!(
+                    self.peek(),
+                    Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                ) {
+                    self.pos += 1;
+                }
+                let number = &self.text[start..self.pos];
+                if !is_number(number) {
+                    self.pos = start;
+                    return Err(self.error("bad number"));
+                }
+                number
+                    .parse()
+                    .map(Value::Number)
+                    .map_err(|_| self.error("bad number"))
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Parses an array or object, whichever starts at the current position.
+    fn container(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Value::Array(items));
+                        }
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    self.expect(":")?;
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Value::Object(members));
+                        }
+                        _ => return Err(self.error("expected , or }")),
+                    }
+                }
+            }
+            _ => Err(self.error("expected [ or {")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut s = String::new();
+        loop {
+            let rest = &self.text[self.pos..];
+            let Some(c) = rest.chars().next() else {
+                return Err(self.error("unclosed string"));
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let escape = self.peek().ok_or_else(|| self.error("unclosed string"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'/' => s.push('/'),
+                        b'b' => s.push('\u{8}'),
+                        b'f' => s.push('\u{c}'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => s.push(self.unicode_escape()?),
+                        _ => return Err(self.error("bad escape")),
+                    }
+                }
+                c if c < ' ' => return Err(self.error("unescaped control character in string")),
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Reads the digits of a `\u` escape, joining surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            self.expect("\\u")?;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("bad surrogate pair"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("bad escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .text
+            .get(self.pos..self.pos + 4)
+            .ok_or_else(|| self.error("bad escape"))?;
+        if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(self.error("bad escape"));
+        }
+        let code = u32::from_str_radix(digits, 16).map_err(|_| self.error("bad escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+/// Whether `s` is a number as JSON writes them: no leading zeros, `+`, or bare decimal point,
+/// all of which Rust's float parsing would let through.
+fn is_number(s: &str) -> bool {
+    fn digits(s: &str) -> (&str, &str) {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        s.split_at(end)
+    }
+
+    let s = s.strip_prefix('-').unwrap_or(s);
+    let (int, rest) = digits(s);
+    if int.is_empty() || (int.len() > 1 && int.starts_with('0')) {
+        return false;
+    }
+    let rest = match rest.strip_prefix('.') {
+        Some(fraction) => match digits(fraction) {
+            ("", _) => return false,
+            (_, rest) => rest,
+        },
+        None => rest,
+    };
+    match rest.strip_prefix(['e', 'E']) {
+        Some(exponent) => {
+            let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            matches!(digits(exponent), (digits, "") if !digits.is_empty())
+        }
+        None => rest.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, write_str, Value};
+
+    fn string(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    #[test]
+    fn strings_round_trip() {
+        let cases = [
+            "",
+            "plain",
+            "quote \" and backslash \\",
+            "new\nline\r\ttab",
+            "control \u{1} \u{1f} \u{7f}",
+            "caf\u{e9} \u{1f3b5} \u{2028}",
+            "/slashes/\\and\\back/",
+        ];
+        for case in cases {
+            let mut out = String::new();
+            write_str(&mut out, case);
+            assert!(!out[1..out.len() - 1].contains(|c: char| c < ' '), "{out}");
+            assert_eq!(parse(&out), Ok(string(case)), "for {out}");
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        let cases = [
+            (r#""\"\\\/\b\f\n\r\t""#, "\"\\/\u{8}\u{c}\n\r\t"),
+            (r#""\u0041\u00e9\u4e2D""#, "A\u{e9}\u{4e2d}"),
+            (r#""\ud83c\udfb5""#, "\u{1f3b5}"),
+            (r#""\uD834\uDD1E after""#, "\u{1d11e} after"),
+            ("\"raw \u{1f3b5} text\"", "raw \u{1f3b5} text"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse(text), Ok(string(expected)), "for {text}");
+        }
+    }
+
+    #[test]
+    fn numbers() {
+        let cases = [
+            ("0", 0.0),
+            ("-0", -0.0),
+            ("7", 7.0),
+            ("-12", -12.0),
+            ("3.25", 3.25),
+            ("1e3", 1000.0),
+            ("2.5E-1", 0.25),
+            ("1e+2", 100.0),
+            ("253.4", 253.4),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse(text), Ok(Value::Number(expected)), "for {text}");
+        }
+        for text in [
+            "01", "-", "1.", ".5", "1e", "1e+", "--1", "1.2.3", "+1", "0x10",
+        ] {
+            assert!(parse(text).is_err(), "{text} parsed");
+        }
+    }
+
+    #[test]
+    fn nesting() {
+        let document = parse(
+            r#" { "version": 1, "tracks": [ {"path": "a", "tags": {"x": [null, true, false]}},
+                {"path": "b", "empty": {}, "none": []} ] } "#,
+        )
+        .unwrap();
+        let tracks = document.get("tracks").and_then(Value::as_array).unwrap();
+        assert_eq!(document.get("version").and_then(Value::as_f64), Some(1.0));
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(tracks[1].get("path").and_then(Value::as_str), Some("b"));
+        assert_eq!(
+            tracks[0].get("tags").and_then(|tags| tags.get("x")),
+            Some(&Value::Array(vec![
+                Value::Null,
+                Value::Bool(true),
+                Value::Bool(false)
+            ]))
+        );
+        assert_eq!(tracks[1].get("empty"), Some(&Value::Object(Vec::new())));
+        assert_eq!(tracks[1].get("none"), Some(&Value::Array(Vec::new())));
+        assert_eq!(tracks[1].get("missing"), None);
+
+        let deep = format!("{}{}", "[".repeat(100), "]".repeat(100));
+        assert!(parse(&deep).is_ok());
+        let deeper = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(parse(&deeper), Err("nested too deeply at byte 128".into()));
+    }
+
+    #[test]
+    fn malformed() {
+        let cases = [
+            ("", "expected a value at byte 0"),
+            ("   ", "expected a value at byte 3"),
+            ("nul", "expected null at byte 0"),
+            ("[1, 2", "expected , or ] at byte 5"),
+            ("[1 2]", "expected , or ] at byte 3"),
+            ("[1,]", "expected a value at byte 3"),
+            ("{\"a\" 1}", "expected : at byte 5"),
+            ("{\"a\": 1,}", "expected \" at byte 8"),
+            ("{a: 1}", "expected \" at byte 1"),
+            ("{\"a\": 1", "expected , or } at byte 7"),
+            ("\"open", "unclosed string at byte 5"),
+            (
+                "\"tab\there\"",
+                "unescaped control character in string at byte 5",
+            ),
+            (r#""\x""#, "bad escape at byte 3"),
+            (r#""\u12""#, "bad escape at byte 3"),
+            (r#""\u+123""#, "bad escape at byte 3"),
+            (r#""\ud83c""#, "expected \\u at byte 7"),
+            (r#""\ud83c\u0041""#, "bad surrogate pair at byte 13"),
+            (r#""\udc00""#, "bad escape at byte 7"),
+            ("01", "bad number at byte 0"),
+            ("1 2", "unexpected text after document at byte 2"),
+            ("{} []", "unexpected text after document at byte 3"),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse(text), Err(expected.into()), "for {text:?}");
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::{config::Config, plan::OnConflict, profile::FsProfile, walk, Error, Result};
+
+/// The name of the files that pin settings for the tree they sit in.
+pub const FILE_NAME: &str = ".tagname.toml";
+
+/// Settings pinned by a library's `.tagname.toml`, so that runs from anywhere inside the tree
+/// name files alike. A template given on the command line (or with `--template-file`) still wins;
+/// the filesystem profile and conflict policy can only be set here.
+///
+/// The file takes the same syntax as the user's configuration file:
+///
+/// ```toml
+/// template = "%albumartist/%album/%track %title"
+/// fs_profile = "windows"
+/// on_conflict = "suffix"
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LibraryConfig {
+    pub template: Option<String>,
+    pub fs_profile: Option<FsProfile>,
+    pub on_conflict: Option<OnConflict>,
+}
+
+impl LibraryConfig {
+    /// Finds the library config that governs the given paths, looking in each path's directory
+    /// and then upward, as editors do with `.editorconfig`.
+    ///
+    /// Paths governed by different files, or some by a file and some by none, can't share one
+    /// set of settings, so they're refused.
+    pub fn find(paths: &[String]) -> Result<Option<Self>> {
+        let cwd = env::current_dir()?;
+        let mut found: Option<Option<PathBuf>> = None;
+        for path in paths {
+            let path = walk::normalize(&cwd.join(path));
+            let start = if path.is_dir() {
+                &*path
+            } else {
+                path.parent().unwrap_or(&path)
+            };
+            let file = start
+                .ancestors()
+                .map(|dir| dir.join(FILE_NAME))
+                .find(|file| file.is_file());
+
+            match &found {
+                Some(previous) if *previous != file => {
+                    let describe = |file: &Option<PathBuf>| match file {
+                        Some(file) => file.display().to_string(),
+                        None => "no library config".into(),
+                    };
+                    return Err(Error::Config(format!(
+                        "the paths are governed by different library configs ({} and {}); run them separately",
+                        describe(previous),
+                        describe(&file)
+                    )));
+                }
+                _ => found = Some(file),
+            }
+        }
+
+        found.flatten().map(|file| Self::read(&file)).transpose()
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+        let bad = |message: String| Error::Config(format!("{}: {message}", path.display()));
+        let config = Config::parse(&text).map_err(|e| match e {
+            Error::Config(message) => bad(message),
+            e => e,
+        })?;
+
+        let string = |key: &str| match config.string(key) {
+            Ok(value) => Ok(value),
+            Err(Error::Config(message)) => Err(bad(message)),
+            Err(e) => Err(e),
+        };
+
+        Ok(Self {
+            template: string("template")?.map(str::to_owned),
+            fs_profile: choice("fs_profile", string("fs_profile")?).map_err(bad)?,
+            on_conflict: choice("on_conflict", string("on_conflict")?).map_err(bad)?,
+        })
+    }
+}
+
+/// Parses a setting that takes one of a command-line option's values.
+fn choice<T: ValueEnum>(key: &str, value: Option<&str>) -> std::result::Result<Option<T>, String> {
+    value
+        .map(|value| T::from_str(value, false).map_err(|e| format!("{key}: {e}")))
+        .transpose()
+}
@@ -0,0 +1,117 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use crate::{
+    json,
+    plan::{Entry, Mode, Status},
+    Error,
+};
+
+/// A file that collects a line of JSON for every change made to the filesystem, across runs.
+///
+/// Each line is an object with the time (RFC 3339, UTC), the action (`rename`, `move`, `copy`,
+/// `hardlink`, `retag`, `breadcrumb`, or `lyrics`), the source and destination, and the outcome
+/// (`ok` or `failed`, with the error). Lines are only ever appended.
+pub struct Log {
+    file: File,
+}
+
+impl Log {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+
+    pub fn record(
+        &mut self,
+        action: &str,
+        source: &Path,
+        target: Option<&Path>,
+        error: Option<&Error>,
+    ) -> io::Result<()> {
+        let mut line = String::from("{\"time\":");
+        json::write_str(&mut line, &timestamp(SystemTime::now()));
+        line.push_str(",\"action\":");
+        json::write_str(&mut line, action);
+        line.push_str(",\"src\":");
+        json::write_str(&mut line, &source.to_string_lossy());
+        line.push_str(",\"dst\":");
+        match target {
+            Some(target) => json::write_str(&mut line, &target.to_string_lossy()),
+            None => line.push_str("null"),
+        }
+        line.push_str(",\"outcome\":");
+        match error {
+            None => line.push_str("\"ok\""),
+            Some(e) => {
+                line.push_str("\"failed\",\"error\":{\"kind\":");
+                json::write_str(&mut line, e.kind());
+                line.push_str(",\"message\":");
+                json::write_str(&mut line, &e.to_string());
+                line.push('}');
+            }
+        }
+        line.push_str("}\n");
+
+        // One write per line, so that runs sharing a log don't interleave within a line.
+        self.file.write_all(line.as_bytes())
+    }
+
+    /// Records the outcome of carrying out an entry's planned change, if it makes one. The
+    /// entry's status has to be the planned one still, since failures overwrite it.
+    pub fn record_change(
+        &mut self,
+        entry: &Entry,
+        mode: &Mode,
+        error: Option<&Error>,
+    ) -> io::Result<()> {
+        let action = match (entry.status, mode) {
+            (Status::Renamed, Mode::Rename) => "rename",
+            (Status::Renamed, _) => "move",
+            (Status::Copied, _) => "copy",
+            (Status::Hardlinked, _) => "hardlink",
+            _ => return Ok(()),
+        };
+        let target = match entry.status {
+            Status::Hardlinked => entry.duplicate.as_deref(),
+            _ => entry.target.as_deref(),
+        };
+        self.record(action, &entry.source, target, error)
+    }
+}
+
+/// Writes a time as RFC 3339 in UTC, to the second.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // Converts days since 1970-01-01 to a date in the proleptic Gregorian calendar.
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
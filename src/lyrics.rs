@@ -0,0 +1,86 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    meta,
+    native::{Lyrics, NativeTag},
+};
+
+/// Writes a track's embedded lyrics to a file beside it with the same name: `.lrc` for timed
+/// lyrics, `.txt` for plain ones.
+///
+/// Returns the sidecar written, or `None` if the track has no lyrics or the sidecar already holds
+/// them. A sidecar holding anything else is never replaced.
+pub fn export(track: &Path) -> io::Result<Option<PathBuf>> {
+    let Some(lyrics) = NativeTag::read(track).and_then(|tag| tag.lyrics()) else {
+        return Ok(None);
+    };
+
+    let (extension, text) = match lyrics {
+        Lyrics::Synced(lines) => ("lrc", lrc(&lines)),
+        Lyrics::Plain(text) => ("txt", text + "\n"),
+    };
+    let sidecar = sidecar_path(track, extension);
+    let mut file = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&sidecar)
+    {
+        Ok(file) => file,
+        // Left by an earlier run.
+        Err(e)
+            if e.kind() == io::ErrorKind::AlreadyExists
+                && fs::read_to_string(&sidecar).is_ok_and(|existing| existing == text) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => {
+            return Err(io::Error::new(
+                e.kind(),
+                format!("{}: {e}", sidecar.display()),
+            ))
+        }
+    };
+    file.write_all(text.as_bytes())?;
+    Ok(Some(sidecar))
+}
+
+/// Writes timed lines in the LRC format: `[mm:ss.xx]text`.
+fn lrc(lines: &[(u32, String)]) -> String {
+    let mut out = String::new();
+    for (ms, text) in lines {
+        let centis = ms / 10;
+        out += &format!(
+            "[{:02}:{:02}.{:02}]{}\n",
+            centis / 6000,
+            centis / 100 % 60,
+            centis % 100,
+            text.trim_end()
+        );
+    }
+    out
+}
+
+/// The track's path with its whole suffix, such as `.flac` or `.flac.bak`, replaced.
+fn sidecar_path(track: &Path, extension: &str) -> PathBuf {
+    let parts = meta::suffix(track).map_or(0, |suffix| {
+        suffix
+            .as_encoded_bytes()
+            .iter()
+            .filter(|&&b| b == b'.')
+            .count()
+            + 1
+    });
+    let mut stem = Path::new(track.file_name().unwrap_or_default());
+    for _ in 0..parts {
+        stem = Path::new(stem.file_stem().unwrap_or_default());
+    }
+
+    let mut name = stem.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    track.with_file_name(name)
+}
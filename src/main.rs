@@ -1,163 +1,748 @@
-use std::{borrow::Cow, ffi::OsString, fmt, fs, io, path::Path, process, str::FromStr};
+mod album;
+mod apply;
+mod audio;
+mod audit;
+mod catalog;
+mod config;
+mod dedupe;
+mod diff;
+mod drm;
+mod encoding;
+mod format;
+mod http;
+mod ignore;
+mod interactive;
+mod interrupt;
+mod json;
+mod library;
+mod log;
+mod lyrics;
+mod meta;
+mod mp4;
+mod native;
+mod notify;
+mod output;
+mod pattern;
+mod plan;
+mod profile;
+mod readonly;
+mod report;
+mod select;
+mod tags;
+mod verify;
+mod walk;
 
-use audiotags::AudioTag;
-use clap::Parser;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use format::{Format, Tag};
+use library::LibraryConfig;
+use native::NativeTag;
+use plan::{Dedupe, Mode, SortInput, Status};
 use regex::Regex;
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
 #[derive(Debug, thiserror::Error)]
-enum Error {
+pub enum Error {
     #[error(transparent)]
     AudioTags(#[from] audiotags::Error),
 
+    #[error("bad catalog: {0}")]
+    Catalog(String),
+
+    #[error("bad config: {0}")]
+    Config(String),
+
+    #[error("protected by DRM")]
+    DrmProtected,
+
     #[error("bad format key: {0}")]
     Format(String),
 
     #[error(transparent)]
     IO(#[from] io::Error),
 
+    #[error("bad modifier: {0}")]
+    Modifier(String),
+
     #[error("missing required tag: {0}")]
     MissingTag(Tag),
-}
 
-#[derive(Debug, Clone, Copy)]
-enum Tag {
-    Album,
-    Artist,
-    Title,
-    Track,
-    Year,
-}
+    #[error("bad movement numbers in {0}")]
+    Movements(String),
 
-impl Tag {
-    fn read_from(self, meta: &dyn AudioTag) -> Result<Cow<'_, str>> {
-        match self {
-            Tag::Album => Ok(meta.album().ok_or(Error::MissingTag(self))?.title.into()),
-            Tag::Artist => meta.artist().map(Cow::from).ok_or(Error::MissingTag(self)),
-            Tag::Title => meta.title().map(Cow::from).ok_or(Error::MissingTag(self)),
-            Tag::Track => Ok(meta
-                .track_number()
-                .ok_or(Error::MissingTag(self))?
-                .to_string()
-                .into()),
-            Tag::Year => Ok(meta
-                .year()
-                .ok_or(Error::MissingTag(self))?
-                .to_string()
-                .into()),
-        }
-    }
-}
+    #[error("compilation has no album artist tag")]
+    NoAlbumArtist,
 
-impl FromStr for Tag {
-    type Err = Error;
+    #[error("would rename {1} of the {2} files under {} (raise --max-change to allow it)", .0.display())]
+    MassChange(PathBuf, usize, usize),
 
-    fn from_str(s: &str) -> Result<Self> {
-        let s = s.trim_start_matches('%');
-        match s {
-            "album" => Ok(Tag::Album),
-            "artist" => Ok(Tag::Artist),
-            "title" => Ok(Tag::Title),
-            "track" => Ok(Tag::Track),
-            "year" => Ok(Tag::Year),
-            _ => Err(Error::Format(s.into())),
-        }
-    }
+    #[error("unfinished download")]
+    Partial,
+
+    #[error("bad plan: {0}")]
+    Plan(String),
+
+    #[error("inside protected directory {} (use --allow-protected to change it)", .0.display())]
+    Protected(PathBuf),
+
+    #[error("{} is read-only (use --clear-readonly to change it anyway)", .0.display())]
+    ReadOnly(PathBuf),
+
+    #[error("couldn't write tags: {0}")]
+    Retag(String),
+
+    #[error("{} isn't planned as it was in the saved plan; preview it again", .0.display())]
+    StalePlan(PathBuf),
+
+    #[error("gave up reading tags after {}s", .0.as_secs())]
+    Timeout(Duration),
+
+    #[error("name would be {0} levels deep, more than the {1} allowed (see --max-depth)")]
+    TooDeep(usize, usize),
+
+    #[error("held back from {}, which couldn't be completed", .0.display())]
+    Unpublished(PathBuf),
+
+    #[error("generated name {0:?} has an empty, \".\", or \"..\" part")]
+    UnsafeName(String),
+
+    #[error("unsupported format: {0}")]
+    UnsupportedFormat(String),
 }
 
-impl fmt::Display for Tag {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl Error {
+    /// A stable, machine-readable name for the kind of error.
+    fn kind(&self) -> &'static str {
         match self {
-            Tag::Album => f.write_str("Album"),
-            Tag::Artist => f.write_str("Artist"),
-            Tag::Title => f.write_str("Title"),
-            Tag::Track => f.write_str("Track"),
-            Tag::Year => f.write_str("Year"),
+            Error::AudioTags(_) => "audio_tags",
+            Error::Catalog(_) => "catalog",
+            Error::Config(_) => "config",
+            Error::DrmProtected => "drm_protected",
+            Error::Format(_) => "format",
+            Error::IO(_) => "io",
+            Error::Modifier(_) => "modifier",
+            Error::MissingTag(_) => "missing_tag",
+            Error::Movements(_) => "movements",
+            Error::MassChange(..) => "mass_change",
+            Error::NoAlbumArtist => "no_album_artist",
+            Error::Partial => "partial",
+            Error::Plan(_) => "plan",
+            Error::Protected(_) => "protected",
+            Error::ReadOnly(_) => "read_only",
+            Error::Retag(_) => "retag",
+            Error::StalePlan(_) => "stale_plan",
+            Error::Timeout(_) => "timeout",
+            Error::TooDeep(..) => "too_deep",
+            Error::Unpublished(_) => "unpublished",
+            Error::UnsafeName(_) => "unsafe_name",
+            Error::UnsupportedFormat(_) => "unsupported_format",
         }
     }
 }
 
 #[derive(Debug, Parser)]
-#[command(author, version)]
+#[command(
+    author,
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    template: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(required_unless_present = "template_file")]
+    template: Option<String>,
     paths: Vec<String>,
 
+    /// read the template from this file ("-" for stdin); every positional argument is then a path
+    #[arg(short = 't', long, value_name = "FILE")]
+    template_file: Option<PathBuf>,
+
     /// perform rename
     #[arg(short = 'f', long = "force")]
     rename: bool,
+
+    /// perform rename, asking about each change and how to settle each collision
+    #[arg(short = 'i', long)]
+    interactive: bool,
+
+    /// with -i, save each answer to this file and reuse answers saved there by earlier reviews
+    #[arg(long, value_name = "FILE", requires = "interactive")]
+    session: Option<PathBuf>,
+
+    /// move files into this library directory, using the template as a relative path
+    #[arg(long, value_name = "DIR", conflicts_with = "copy_to")]
+    into: Option<PathBuf>,
+
+    /// copy files into this library directory, skipping tracks it already holds
+    #[arg(long, value_name = "DIR")]
+    copy_to: Option<PathBuf>,
+
+    /// handle files whose content already exists at the target or in the destination
+    #[arg(long, value_enum)]
+    dedupe: Option<Dedupe>,
+
+    /// plan files in this order rather than the order they were found in
+    #[arg(long, value_enum, value_name = "ORDER")]
+    sort_input: Option<SortInput>,
+
+    /// search directories without honoring .tagnameignore files
+    #[arg(long)]
+    no_ignore: bool,
+
+    /// resolve symbolic links in the given paths before planning
+    #[arg(long)]
+    canonicalize: bool,
+
+    /// ignore files smaller than this (e.g. 500k, 2M)
+    #[arg(long, value_name = "SIZE", value_parser = walk::parse_size)]
+    min_size: Option<u64>,
+
+    /// only take files modified after this time: an age (e.g. 12h, 7d) or a UTC date (2024-05-01)
+    #[arg(long, value_name = "TIME", value_parser = walk::parse_time)]
+    newer_than: Option<SystemTime>,
+
+    /// only take files modified before this time, written as for --newer-than
+    #[arg(long, value_name = "TIME", value_parser = walk::parse_time)]
+    older_than: Option<SystemTime>,
+
+    /// when a file's tags are damaged, use whatever can be read or guessed from its name
+    #[arg(long)]
+    lenient: bool,
+
+    /// fill in missing tags by matching file names against this pattern (e.g. "%track - %title")
+    #[arg(long, value_name = "PATTERN")]
+    input_pattern: Option<String>,
+
+    /// when renaming, also write tags filled in by --input-pattern into the files
+    #[arg(long, requires = "input_pattern", conflicts_with = "copy_to")]
+    retag: bool,
+
+    /// when renaming, record each file's original path in a custom tag (default ORIGINAL_PATH)
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "ORIGINAL_PATH")]
+    breadcrumb: Option<String>,
+
+    /// when renaming, also write embedded lyrics beside each track as .lrc or .txt files
+    #[arg(long)]
+    export_lyrics: bool,
+
+    /// write the tags of every file read to this file, for planning later runs with --catalog
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["catalog", "rename", "interactive"])]
+    export_catalog: Option<PathBuf>,
+
+    /// preview from tags saved with --export-catalog instead of reading the files; nothing on disk
+    /// is looked at, so collisions with existing files and read-only sources aren't caught
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = [
+            "rename",
+            "interactive",
+            "missing_art_marker",
+            "missing_art_dir",
+            "diff"
+        ]
+    )]
+    catalog: Option<PathBuf>,
+
+    /// give up on a file whose tags take longer than this many seconds to read
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+
+    /// append this to the folder name of albums whose folders hold no cover image
+    #[arg(long, value_name = "TEXT", conflicts_with = "missing_art_dir")]
+    missing_art_marker: Option<String>,
+
+    /// put albums whose folders hold no cover image under this directory instead
+    #[arg(long, value_name = "DIR")]
+    missing_art_dir: Option<PathBuf>,
+
+    /// change files inside directories the config file marks as protected
+    #[arg(long)]
+    allow_protected: bool,
+
+    /// make read-only files and directories writable for the rename, then read-only again
+    #[arg(long)]
+    clear_readonly: bool,
+
+    /// refuse to rename in place more than this percentage of the files under a directory of 50
+    /// or more; off unless given
+    #[arg(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    max_change: Option<u8>,
+
+    /// fail files whose generated names have more than this many parts, counting the file name
+    #[arg(long, value_name = "N", default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    max_depth: u16,
+
+    /// don't notify the media servers named in the config file after renaming
+    #[arg(long)]
+    no_notify: bool,
+
+    /// number of parallel workers for file operations (one directory per worker at a time)
+    #[arg(short = 'j', long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+
+    /// append a line of JSON for every change made to this file, for auditing across runs
+    #[arg(long, value_name = "FILE")]
+    log: Option<PathBuf>,
+
+    /// write an HTML report of the run to this file
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+
+    /// show tag and audio differences between files that collide
+    #[arg(long)]
+    diff: bool,
+
+    /// number each entry of the preview for use with --indices, and show how long each name is
+    #[arg(short = 'v', long)]
+    verbose: bool,
+
+    /// change only the entries with these numbers in the plan given with --plan (e.g. 1,5,9-20)
+    #[arg(
+        long,
+        value_name = "LIST",
+        value_parser = select::parse_indices,
+        requires = "saved_plan"
+    )]
+    indices: Option<select::Indices>,
+
+    /// a preview saved with --output json, which --indices numbers entries of; the picked files
+    /// must still be planned the same way
+    #[arg(long = "plan", value_name = "FILE")]
+    saved_plan: Option<PathBuf>,
+
+    /// change only files whose current or planned path matches this glob (may be repeated)
+    #[arg(long, value_name = "PATTERN", value_parser = select::parse_glob)]
+    only: Vec<Regex>,
+
+    /// output format
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    output: Output,
+
+    /// send the output to a file or POST it to an http:// URL instead of printing it ("-")
+    #[arg(long, value_name = "FILE|URL", default_value = "-", value_parser = output::parse_sink)]
+    output_to: output::Sink,
+
+    /// print a shell script that performs the plan, instead of the usual output
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SHELL",
+        conflicts_with_all = ["rename", "interactive"]
+    )]
+    emit_script: Option<output::Shell>,
 }
 
-#[derive(Debug, Clone)]
-enum Element {
-    Tag(Tag),
-    Literal(String),
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// show each file's tags and chapters
+    Tags { paths: Vec<String> },
+
+    /// report files with missing tags
+    Audit {
+        paths: Vec<String>,
+
+        /// also report files without ReplayGain tags
+        #[arg(long)]
+        replaygain: bool,
+    },
+
+    /// check that a plan saved with --output json was carried out
+    Verify { plan: PathBuf },
 }
 
-#[derive(Debug, Clone)]
-struct Format {
-    elements: Vec<Element>,
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Output {
+    Text,
+    Json,
+    Markdown,
 }
 
-impl Format {
-    fn from_template(template: &str) -> Result<Self> {
-        let rx = Regex::new(r#"(%[a-z]+)|([^%]+)"#).unwrap();
-        let elements: Result<Vec<_>> = rx
-            .captures_iter(template)
-            .map(|cx| {
-                if let Some(tag) = cx.get(1) {
-                    tag.as_str().parse::<Tag>().map(Element::Tag)
-                } else {
-                    Ok(Element::Literal(cx.get(2).unwrap().as_str().into()))
+fn main() {
+    let args = Args::parse_from(wild::args());
+    let result = match &args.command {
+        Some(Command::Tags { paths }) => tags::run(paths),
+        Some(Command::Audit { paths, replaygain }) => {
+            let checks = audit::Checks {
+                replaygain: *replaygain,
+            };
+            audit::run(paths, checks).map(|passed| {
+                if !passed {
+                    process::exit(1);
                 }
             })
-            .collect();
+        }
+        Some(Command::Verify { plan }) => verify::run(plan).map(|passed| {
+            if !passed {
+                process::exit(1);
+            }
+        }),
+        None => run(args),
+    };
 
-        Ok(Self {
-            elements: elements?,
-        })
+    if let Err(e) = result {
+        eprintln!("{e}");
+        process::exit(1);
     }
+}
 
-    fn build_name(&self, meta: &dyn AudioTag) -> Result<String> {
-        let mut f = String::new();
+fn run(mut args: Args) -> Result<()> {
+    // The first positional is a path only when the template has to come from elsewhere: a
+    // template file, or a library config governing that path and the rest. Anything else, even
+    // without keys, is the template it always was.
+    let library = match args.template.take() {
+        None => LibraryConfig::find(&args.paths)?,
+        Some(first) if args.template_file.is_some() => {
+            args.paths.insert(0, first);
+            LibraryConfig::find(&args.paths)?
+        }
+        Some(first) => {
+            let mut paths = args.paths.clone();
+            paths.insert(0, first.clone());
+            let found = if Path::new(&first).exists() {
+                LibraryConfig::find(&paths)?
+            } else {
+                None
+            };
+            if found
+                .as_ref()
+                .is_some_and(|library| library.template.is_some())
+            {
+                args.paths = paths;
+                found
+            } else {
+                args.template = Some(first);
+                LibraryConfig::find(&args.paths)?
+            }
+        }
+    }
+    .unwrap_or_default();
+    let template = match (&args.template_file, args.template.take(), library.template) {
+        (Some(path), ..) => read_template(path)?,
+        (None, Some(template), _) => template,
+        (None, None, Some(template)) => template,
+        (None, None, None) => {
+            return Err(Error::Config(format!(
+                "no template given, and no {} with one governs these paths",
+                library::FILE_NAME
+            )))
+        }
+    };
 
-        for element in &self.elements {
-            match element {
-                Element::Tag(tag) => f += &*tag.read_from(meta)?,
-                Element::Literal(lit) => f += lit,
+    let config = config::Config::load()?;
+    let notifiers = notify::Notifiers::from_config(&config)?;
+    let template = Format::expand_aliases(&template, &config.table("aliases")?)?;
+    let format =
+        Format::from_template(&template)?.with_profile(library.fs_profile.unwrap_or_default());
+    let mode = match (args.into, args.copy_to) {
+        (Some(root), _) => Mode::Move(root),
+        (_, Some(root)) => Mode::Copy(root),
+        _ => Mode::Rename,
+    };
+    let filter = walk::Filter {
+        min_size: args.min_size,
+        newer_than: args.newer_than,
+        older_than: args.older_than,
+        no_ignore: args.no_ignore,
+    };
+    if args.canonicalize {
+        for path in &mut args.paths {
+            // Missing paths are left for the reader to report.
+            if let Some(canonical) = fs::canonicalize(&*path)
+                .ok()
+                .map(walk::simplify)
+                .and_then(|canonical| canonical.to_str().map(str::to_owned))
+            {
+                *path = canonical;
             }
         }
+    }
+    let mut catalog = None;
+    let paths = match &args.catalog {
+        // The catalog stands in for the walk as well as the reads, so the files are never
+        // touched; paths only narrow it down.
+        Some(file) => {
+            if args.sort_input == Some(SortInput::Mtime) {
+                let message = "it records no modification times for --sort-input mtime".into();
+                return Err(Error::Catalog(message));
+            }
+            let cwd = env::current_dir()?;
+            let roots: Vec<_> = args
+                .paths
+                .iter()
+                .map(|path| walk::normalize(&cwd.join(path)))
+                .collect();
+            let tracks: Vec<_> = catalog::load(file)?
+                .into_iter()
+                .filter(|(path, _)| roots.is_empty() || roots.iter().any(|r| path.starts_with(r)))
+                .collect();
+            let paths = tracks.iter().map(|(path, _)| path.clone()).collect();
+            catalog = Some(tracks.into_iter().collect());
+            paths
+        }
+        None => walk::expand(&args.paths, &filter)?,
+    };
+    let options = plan::Options {
+        mode,
+        dedupe: args.dedupe,
+        lenient: args.lenient,
+        input_pattern: args
+            .input_pattern
+            .as_deref()
+            .map(pattern::InputPattern::parse)
+            .transpose()?,
+        timeout: args.timeout.map(Duration::from_secs),
+        missing_art: match (args.missing_art_marker, args.missing_art_dir) {
+            (Some(marker), _) => Some(plan::MissingArt::Marker(marker)),
+            (_, Some(dir)) => Some(plan::MissingArt::Review(dir)),
+            _ => None,
+        },
+        protected: if args.allow_protected {
+            Vec::new()
+        } else {
+            config.paths("protect")?
+        },
+        on_conflict: library.on_conflict.unwrap_or_default(),
+        sort: args.sort_input,
+        catalog,
+        clear_readonly: args.clear_readonly,
+        max_depth: args.max_depth.into(),
+    };
+    let mut entries = plan::build(&format, &paths, &options)?;
 
-        Ok(f)
+    if let Some(file) = &args.export_catalog {
+        let cwd = env::current_dir()?;
+        let tracks: Vec<_> = entries
+            .iter()
+            .filter_map(|entry| {
+                let tags = entry.tags.as_ref()?;
+                Some((walk::normalize(&cwd.join(&entry.source)), tags))
+            })
+            .collect();
+        catalog::export(file, &tracks)?;
     }
-}
 
-fn main() {
-    if let Err(e) = run(Args::parse_from(wild::args())) {
-        eprintln!("{e}");
-        process::exit(1);
+    if args.indices.is_some() || !args.only.is_empty() {
+        let picks = match (&args.indices, &args.saved_plan) {
+            (Some(indices), Some(plan)) => Some(select::picks(plan, indices)?),
+            _ => None,
+        };
+        select::apply(&mut entries, picks.as_deref(), &args.only)?;
     }
-}
 
-fn run(args: Args) -> Result<()> {
-    let format = Format::from_template(&args.template)?;
-    for path in &args.paths {
-        let path = Path::new(path);
-        let meta = audiotags::Tag::new().read_from_path(path)?;
+    let roots: Vec<_> = args
+        .paths
+        .iter()
+        .map(|path| walk::normalize(Path::new(path)))
+        .collect();
+    // Imports move everything by design; only renames in place are expected to change little.
+    let sweep = match options.mode {
+        // A catalog's paths needn't exist here, and a catalog plan can only be previewed anyway.
+        Mode::Rename if options.catalog.is_some() => None,
+        Mode::Rename => args
+            .max_change
+            .and_then(|max| plan::sweeping(&entries, &roots, max)),
+        Mode::Move(_) | Mode::Copy(_) => None,
+    };
+    if let Some((root, renamed, total)) = sweep {
+        // Interactive runs confirm each change anyway, so a warning is enough.
+        let e = Error::MassChange(root, renamed, total);
+        if args.rename && !args.interactive {
+            return Err(e);
+        }
+        eprintln!("warning: {e}");
+    }
+
+    if args.interactive {
+        interactive::review(&mut entries, &options.mode, args.session.as_deref())?;
+        // Overwriting, suffixing, and settled collisions make changes the plan never checked.
+        plan::guard(&mut entries, &options);
+        args.rename = true;
+    }
 
-        let mut name = OsString::from(format.build_name(meta.as_ref())?);
-        if let Some(extension) = path.extension() {
-            name.push(".");
-            name.push(extension);
+    if args.rename {
+        let mut log = args.log.as_deref().map(log::Log::open).transpose()?;
+        let guard = interrupt::Guard::install();
+        if args.retag {
+            // Changes inside protected directories are already held back, but files left where
+            // they are still need checking.
+            let inside = plan::protection(&options.protected, true);
+            for entry in entries.iter_mut() {
+                if interrupt::requested() {
+                    break;
+                }
+                // Files the plan leaves out, for whatever reason, are left exactly as they are.
+                let kept = entry.status.is_change() || entry.status == Status::Unchanged;
+                if let (Some(inferred), true) = (&entry.inferred, kept) {
+                    if let Some(root) = inside(&entry.source) {
+                        let e = Error::Protected(root);
+                        eprintln!("{}: couldn't write tags: {e}", entry.source.display());
+                        continue;
+                    }
+                    let result = native::write(&entry.source, inferred);
+                    if let Some(log) = &mut log {
+                        log.record("retag", &entry.source, None, result.as_ref().err())?;
+                    }
+                    entry.complete(result);
+                }
+            }
         }
+        // The workers record each change as it's made, so that a run that never finishes still
+        // leaves a record of what it did.
+        let shared = log.take().map(Mutex::new);
+        let logged = apply::run(
+            &mut entries,
+            &options.mode,
+            args.jobs,
+            args.clear_readonly,
+            shared.as_ref(),
+        );
+        log = shared.map(|log| log.into_inner().unwrap());
+        logged?;
+        if let Some(name) = &args.breadcrumb {
+            // Files that already carry a breadcrumb keep it, since it names where they came from
+            // before any run moved them.
+            let cwd = env::current_dir()?;
+            for entry in entries.iter().filter(|e| e.status.is_change()) {
+                if interrupt::requested() {
+                    break;
+                }
+                let target = entry.target.as_deref().unwrap();
+                if NativeTag::read(target).is_some_and(|tag| tag.custom(name).is_some()) {
+                    continue;
+                }
+                let original = walk::normalize(&cwd.join(&entry.source));
+                let result = native::write_custom(target, name, &original.to_string_lossy());
+                if let Some(log) = &mut log {
+                    log.record("breadcrumb", target, None, result.as_ref().err())?;
+                }
+                if let Err(e) = result {
+                    eprintln!(
+                        "{}: couldn't record the original path: {e}",
+                        target.display()
+                    );
+                }
+            }
+        }
+        if args.export_lyrics {
+            // Files left where they are get sidecars too, but not inside protected directories.
+            let inside = plan::protection(&options.protected, true);
+            for entry in &entries {
+                let track = match entry.status {
+                    status if status.is_change() => entry.target.as_deref().unwrap(),
+                    Status::Unchanged => &entry.source,
+                    _ => continue,
+                };
+                if interrupt::requested() {
+                    break;
+                }
+                let exported = match inside(track) {
+                    Some(root) => Err(Error::Protected(root)),
+                    None => lyrics::export(track).map_err(Error::from),
+                };
+                match exported {
+                    Ok(Some(sidecar)) => {
+                        if let Some(log) = &mut log {
+                            log.record("lyrics", track, Some(&sidecar), None)?;
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("{}: couldn't export lyrics: {e}", track.display()),
+                }
+            }
+        }
+        let changed = entries
+            .iter()
+            .any(|e| e.status.is_change() || e.status == Status::Hardlinked);
+        if changed && !args.no_notify {
+            notifiers.run();
+        }
+        drop(guard);
+    }
 
-        let new_path = path.with_file_name(name);
-        if args.rename {
-            fs::rename(path, new_path)?;
-        } else {
-            println!("{}", new_path.display());
+    if let Some(path) = &args.report {
+        report::write(path, &entries, !args.rename)?;
+    }
+
+    // Printed output goes out as it's made, keeping its place among the messages on stderr.
+    let mut buffer = Vec::new();
+    let mut stdout = io::stdout().lock();
+    let out: &mut dyn io::Write = match args.output_to {
+        output::Sink::Stdout => &mut stdout,
+        _ => &mut buffer,
+    };
+    let content_type = match args.output {
+        _ if args.emit_script.is_some() => {
+            let on_disk = options.catalog.is_none();
+            output::script(out, &entries, args.emit_script.unwrap(), on_disk)?;
+            "text/plain"
+        }
+        Output::Text => {
+            output::text(out, &entries, !args.rename, args.diff, args.verbose)?;
+            // A catalog says nothing about the directories, and they mightn't even be here.
+            if !args.rename && options.catalog.is_none() {
+                let (created, emptied) = plan::churn(&entries);
+                let noun = if created == 1 {
+                    "directory"
+                } else {
+                    "directories"
+                };
+                if created + emptied > 0 {
+                    eprintln!("would create {created} {noun} and leave {emptied} empty");
+                }
+            }
+            "text/plain"
+        }
+        Output::Json => {
+            output::json(out, &entries, !args.rename)?;
+            "application/json"
         }
+        Output::Markdown => {
+            output::markdown(out, &entries)?;
+            "text/markdown"
+        }
+    };
+    args.output_to.deliver(&buffer, content_type)?;
+
+    let interrupted = entries
+        .iter()
+        .filter(|e| e.status == Status::Interrupted)
+        .count();
+    if interrupted > 0 {
+        eprintln!(
+            "interrupted before {interrupted} of the planned changes; run the same command again to finish"
+        );
+        process::exit(130);
+    }
+    if entries.iter().any(|e| e.status.is_failure()) {
+        process::exit(1);
     }
     Ok(())
 }
+
+/// Reads a template from a file, or from stdin given `-`.
+///
+/// Lines are joined without separators, so a long template can be split wherever it reads best.
+/// Lines starting with `#` are comments.
+fn read_template(path: &Path) -> io::Result<String> {
+    let text = if path == Path::new("-") {
+        io::read_to_string(io::stdin())?
+    } else {
+        fs::read_to_string(path)?
+    };
+
+    Ok(text
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .collect())
+}
@@ -0,0 +1,281 @@
+use std::{ffi::OsStr, path::Path};
+
+use audiotags::AudioTag;
+
+use crate::{drm, native::NativeTag, Error, Result};
+
+/// Extensions of the formats audiotags reads.
+const AUDIOTAGS_EXTENSIONS: &[&str] = &["flac", "isom", "m4a", "m4b", "m4p", "m4v", "mp3", "mp4"];
+
+/// Extensions of audio containers with no tag reader yet: DSD streams, SACD images, and Matroska.
+const UNSUPPORTED_EXTENSIONS: &[&str] = &["dff", "dsf", "iso", "mka"];
+
+/// Extensions browsers and download managers add to files they haven't finished writing.
+const PARTIAL_EXTENSIONS: &[&str] = &["!ut", "crdownload", "download", "part", "partial"];
+
+/// What reads the tags of a kind of file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    AudioTags,
+
+    /// An audio container nothing here can read yet. Files are still found, so that they can be
+    /// reported rather than silently passed over.
+    Unsupported,
+
+    /// An unfinished download of an audio file, such as `song.mp3.part`. Found so that it can be
+    /// reported, but never read or renamed.
+    Partial,
+}
+
+impl Backend {
+    /// The backend for a file, or `None` if its extension isn't one of the known audio formats.
+    pub fn for_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        let known = |list: &[&str]| {
+            list.iter()
+                .any(|known| known.eq_ignore_ascii_case(extension))
+        };
+        if known(AUDIOTAGS_EXTENSIONS) {
+            Some(Backend::AudioTags)
+        } else if known(UNSUPPORTED_EXTENSIONS) {
+            Some(Backend::Unsupported)
+        } else if known(PARTIAL_EXTENSIONS) && inner(path).is_some() {
+            Some(Backend::Partial)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether a walk should pick up a file: it's in a format with a backend, or is one with another
+/// extension added, as with `song.mp3.bak`.
+pub fn is_audio(path: &Path) -> bool {
+    Backend::for_path(path).is_some() || inner(path).is_some()
+}
+
+/// The backend for the file a name had before another extension was added to it, as with
+/// `song.flac.bak`.
+fn inner(path: &Path) -> Option<Backend> {
+    let stem = Path::new(path.file_stem()?);
+    stem.extension()?;
+    Backend::for_path(stem).filter(|backend| *backend != Backend::Partial)
+}
+
+/// The end of a file's name that new names keep: its extension, or when that isn't audio but
+/// follows an audio extension, as in `song.flac.bak`, both of them.
+pub fn suffix(path: &Path) -> Option<&OsStr> {
+    let extension = path.extension()?;
+    if Backend::for_path(path).is_some() || inner(path).is_none() {
+        return Some(extension);
+    }
+
+    let name = path.file_name()?.to_str()?;
+    let inner_len = Path::new(path.file_stem()?).extension()?.len();
+    Some(OsStr::new(
+        &name[name.len() - inner_len - 1 - extension.len()..],
+    ))
+}
+
+/// The lower-case extension that says what format a file is in, looking past an added extension
+/// such as `.bak`.
+pub fn format_extension(path: &Path) -> Option<String> {
+    let suffix = suffix(path)?.to_str()?;
+    Some(suffix.split('.').next()?.to_ascii_lowercase())
+}
+
+/// Tag values read from a single file.
+///
+/// Values are copied out of the underlying tag so that every file in a run can be read before
+/// any names are built; album-level facts depend on seeing all of an album's tracks.
+#[derive(Debug, Clone, Default)]
+pub struct Meta {
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u16>,
+    pub total_tracks: Option<u16>,
+    pub disc: Option<u16>,
+    pub total_discs: Option<u16>,
+    pub year: Option<i32>,
+
+    /// Length of the track in seconds.
+    pub duration: Option<f64>,
+
+    /// The classical work the track is part of.
+    pub work: Option<String>,
+
+    /// The name of the movement the track holds, and its number among the work's movements.
+    pub movement: Option<String>,
+    pub movement_number: Option<u16>,
+    pub movement_count: Option<u16>,
+}
+
+impl Meta {
+    /// Reads a file's tags with whichever backend handles its format.
+    ///
+    /// Files of unknown formats are offered to audiotags, which reports them in its own words.
+    pub fn read(path: &Path) -> Result<Self> {
+        match Backend::for_path(path).unwrap_or(Backend::AudioTags) {
+            Backend::AudioTags if drm::is_protected(path) => Err(Error::DrmProtected),
+            Backend::AudioTags => {
+                // audiotags goes by the last extension, which an added `.bak` would hide. The
+                // work and movement fields it doesn't know about come from the tag underneath.
+                let (mut meta, native) = match format_extension(path).as_deref() {
+                    Some("mp3") => {
+                        let tag = audiotags::Id3v2Tag::read_from_path(path)?;
+                        (Self::from_tag(&tag), NativeTag::Id3(tag.into()))
+                    }
+                    Some("flac") => {
+                        let tag = audiotags::FlacTag::read_from_path(path)?;
+                        (Self::from_tag(&tag), NativeTag::Flac(tag.into()))
+                    }
+                    Some("isom" | "m4a" | "m4b" | "m4p" | "m4v" | "mp4") => {
+                        let tag = audiotags::Mp4Tag::read_from_path(path)?;
+                        (Self::from_tag(&tag), NativeTag::Mp4(tag.into()))
+                    }
+                    _ => {
+                        return Ok(Self::from_tag(
+                            audiotags::Tag::new().read_from_path(path)?.as_ref(),
+                        ))
+                    }
+                };
+                native.read_work(&mut meta);
+                Ok(meta)
+            }
+            Backend::Unsupported => Err(Error::UnsupportedFormat(
+                path.extension().unwrap().to_string_lossy().to_lowercase(),
+            )),
+            Backend::Partial => Err(Error::Partial),
+        }
+    }
+
+    /// Salvages what it can from a file whose tags failed to parse.
+    ///
+    /// ID3 tags are read again, keeping the frames that came before the damage. Anything else is
+    /// guessed from the file name and its directory. Returns the tags along with a note saying
+    /// which happened.
+    pub fn recover(path: &Path) -> (Self, &'static str) {
+        if format_extension(path).as_deref() == Some("mp3") {
+            let tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => Some(tag),
+                Err(e) => e.partial_tag,
+            };
+            if let Some(tag) = tag {
+                let tag = audiotags::Id3v2Tag::from(tag);
+                return (Self::from_tag(&tag), "using the tags that could be read");
+            }
+        }
+
+        (Self::guess(path), "guessed tags from the file name")
+    }
+
+    /// Tags guessed from names like `03 - Artist - Title.mp3` inside a directory named for the
+    /// album.
+    fn guess(path: &Path) -> Self {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let stem = match suffix(path).and_then(OsStr::to_str) {
+            Some(suffix) => name[..name.len() - suffix.len() - 1].to_string(),
+            None => name.to_string(),
+        };
+        let digits = stem
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(stem.len());
+        let (track, rest) = match stem[..digits].parse() {
+            Ok(track) => (
+                Some(track),
+                stem[digits..]
+                    .trim_start_matches(|c: char| c == '.' || c == '-' || c.is_whitespace()),
+            ),
+            Err(_) => (None, &*stem),
+        };
+        let (artist, title) = match rest.split_once(" - ") {
+            Some((artist, title)) => (scrub(artist), scrub(title)),
+            None => (None, scrub(rest)),
+        };
+        let album = path
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|name| scrub(&name.to_string_lossy()));
+
+        Self {
+            album,
+            artist,
+            title,
+            track,
+            ..Self::default()
+        }
+    }
+
+    /// The tag values worth showing people, by name, in a fixed order.
+    pub fn fields(&self) -> [(&'static str, Option<String>); 7] {
+        let pair = |number: Option<u16>, total: Option<u16>| match (number, total) {
+            (Some(number), Some(total)) => Some(format!("{number}/{total}")),
+            (number, _) => number.map(|number| number.to_string()),
+        };
+
+        [
+            ("Title", self.title.clone()),
+            ("Artist", self.artist.clone()),
+            ("Album", self.album.clone()),
+            ("Album Artist", self.album_artist.clone()),
+            ("Track", pair(self.track, self.total_tracks)),
+            ("Disc", pair(self.disc, self.total_discs)),
+            ("Year", self.year.map(|year| year.to_string())),
+        ]
+    }
+
+    pub fn from_tag(tag: &dyn AudioTag) -> Self {
+        Self {
+            album: tag.album_title().and_then(scrub),
+            album_artist: tag.album_artist().and_then(scrub),
+            artist: tag.artist().and_then(scrub),
+            title: tag.title().and_then(scrub),
+            track: tag.track_number(),
+            total_tracks: tag.total_tracks(),
+            disc: tag.disc_number(),
+            total_discs: tag.total_discs(),
+            year: tag.year(),
+            duration: duration(tag),
+            ..Self::default()
+        }
+    }
+}
+
+fn duration(tag: &dyn AudioTag) -> Option<f64> {
+    let duration = tag.duration()?;
+
+    // ID3 keeps the length in milliseconds (TLEN), and audiotags passes it through unconverted.
+    if tag.to_any().is::<audiotags::Id3v2Tag>() {
+        Some(duration / 1000.0)
+    } else {
+        Some(duration)
+    }
+}
+
+/// Removes characters that are invisible in a file name but still make it distinct.
+///
+/// Tag editors occasionally leave byte order marks, zero-width spaces, or control characters in
+/// values, and a file name containing one looks identical to one that doesn't. Zero-width
+/// joiners and non-joiners are kept, since some scripts and emoji need them. Values left empty
+/// are treated as missing.
+pub fn scrub(value: &str) -> Option<String> {
+    let scrubbed: String = value.chars().filter(|&c| !is_hidden(c)).collect();
+    let scrubbed = scrubbed.trim();
+    (!scrubbed.is_empty()).then(|| scrubbed.into())
+}
+
+fn is_hidden(c: char) -> bool {
+    c.is_control()
+        || matches!(
+            c,
+            '\u{00AD}' // soft hyphen
+                | '\u{180E}' // mongolian vowel separator
+                | '\u{200B}' // zero-width space
+                | '\u{200E}'..='\u{200F}' // directional marks
+                | '\u{202A}'..='\u{202E}' // directional embeddings and overrides
+                | '\u{2060}'..='\u{2064}' // word joiner and invisible operators
+                | '\u{2066}'..='\u{2069}' // directional isolates
+                | '\u{FEFF}' // byte order mark
+        )
+}
@@ -0,0 +1,316 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Movie boxes larger than this belong to video, not music, and aren't read.
+const MAX_MOOV_SIZE: u64 = 64 << 20;
+
+/// The sample entries of every track in a movie box body: each one's type, such as `mp4a` or
+/// `alac`, and its body. They live in `trak/mdia/minf/stbl/stsd`.
+pub fn sample_entries(moov: &[u8]) -> Vec<([u8; 4], &[u8])> {
+    boxes(moov)
+        .filter(|(name, _)| name == b"trak")
+        .filter_map(|(_, trak)| {
+            let stbl = [b"mdia", b"minf", b"stbl"]
+                .iter()
+                .try_fold(trak, |body, name| child(body, name))?;
+            // The sample description starts with a version, flags, and an entry count.
+            child(stbl, b"stsd")?.get(8..)
+        })
+        .flat_map(boxes)
+        .collect()
+}
+
+/// The boxes inside an audio sample entry, after its fixed fields. QuickTime's version 1 and 2
+/// entries have longer fixed parts than version 0.
+pub fn audio_children(entry: &[u8]) -> Option<&[u8]> {
+    let version = u16::from_be_bytes(entry.get(8..10)?.try_into().unwrap());
+    let fixed = match version {
+        0 => 28,
+        1 => 44,
+        2 => 64,
+        _ => return None,
+    };
+    entry.get(fixed..)
+}
+
+/// The body of the first box named `name` directly inside `body`.
+pub fn child<'a>(body: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(body)
+        .find(|(found, _)| found == name)
+        .map(|(_, body)| body)
+}
+
+/// The boxes laid end to end in `body`, as names and bodies. Stops at the first box whose size
+/// doesn't fit.
+pub fn boxes(body: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = body;
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(rest.get(..4)?.try_into().unwrap());
+        let name: [u8; 4] = rest.get(4..8)?.try_into().unwrap();
+        let (header, size) = match size {
+            0 => (8, rest.len()),
+            1 => (
+                16,
+                usize::try_from(u64::from_be_bytes(rest.get(8..16)?.try_into().unwrap())).ok()?,
+            ),
+            size => (8, size as usize),
+        };
+        if size < header || size > rest.len() {
+            return None;
+        }
+        let body = &rest[header..size];
+        rest = &rest[size..];
+        Some((name, body))
+    })
+}
+
+/// Reads the body of an MP4 file's movie box (`moov`), which describes its tracks.
+pub fn movie_box(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut offset = 0;
+
+    // Walk the top-level boxes looking for the movie box, which may come before or after the
+    // media data.
+    while offset + 8 <= len {
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let mut header_len = 8;
+        let mut size = u64::from(u32::from_be_bytes(header[..4].try_into().unwrap()));
+        if size == 1 {
+            let mut large = [0; 8];
+            file.read_exact(&mut large)?;
+            size = u64::from_be_bytes(large);
+            header_len = 16;
+        } else if size == 0 {
+            size = len - offset;
+        }
+        if size < header_len {
+            return Ok(None);
+        }
+
+        if &header[4..] == b"moov" {
+            let body = size - header_len;
+            if body > MAX_MOOV_SIZE {
+                return Ok(None);
+            }
+            let mut moov = vec![0; body as usize];
+            file.read_exact(&mut moov)?;
+            return Ok(Some(moov));
+        }
+
+        offset += size;
+        file.seek(SeekFrom::Start(offset))?;
+    }
+    Ok(None)
+}
+
+/// Text samples longer than this aren't chapter titles, and aren't read.
+const MAX_TITLE_SIZE: u32 = 4096;
+
+/// A chapter of an MP4 file, as in an .m4b audiobook.
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    /// Start time in milliseconds.
+    pub start: f64,
+    pub title: String,
+}
+
+/// Reads an MP4 file's chapters, in order.
+///
+/// Two layouts are in use: a Nero chapter list (`moov/udta/chpl`), written by many encoders, and
+/// a QuickTime chapter track, a text track that the audio track points at (`tref/chap`), written
+/// by Apple's tools. The list is preferred when a file has both.
+pub fn chapters(path: &Path) -> io::Result<Vec<Chapter>> {
+    let Some(moov) = movie_box(path)? else {
+        return Ok(Vec::new());
+    };
+    let list = child(&moov, b"udta")
+        .and_then(|udta| child(udta, b"chpl"))
+        .and_then(nero_chapters)
+        .unwrap_or_default();
+    if !list.is_empty() {
+        return Ok(list);
+    }
+    chapter_track(path, &moov)
+}
+
+/// Parses a Nero chapter list: start times in units of 100 nanoseconds, each with a title of up
+/// to 255 bytes.
+fn nero_chapters(chpl: &[u8]) -> Option<Vec<Chapter>> {
+    let version = *chpl.first()?;
+    // Version 1 lists have four more bytes, whose meaning isn't documented, before the count.
+    let mut pos = if version == 0 { 4 } else { 8 };
+    let count = *chpl.get(pos)?;
+    pos += 1;
+
+    let mut chapters = Vec::new();
+    for _ in 0..count {
+        let start = u64_at(chpl, pos)?;
+        let len = usize::from(*chpl.get(pos + 8)?);
+        let title = chpl.get(pos + 9..pos + 9 + len)?;
+        pos += 9 + len;
+        chapters.push(Chapter {
+            start: start as f64 / 10_000.0,
+            title: String::from_utf8_lossy(title).into_owned(),
+        });
+    }
+    Some(chapters)
+}
+
+/// Reads the titles of the text track that an audio track names as its chapters. Each sample of
+/// that track is one chapter, starting when the sample does.
+fn chapter_track(path: &Path, moov: &[u8]) -> io::Result<Vec<Chapter>> {
+    let traks: Vec<_> = boxes(moov)
+        .filter(|(name, _)| name == b"trak")
+        .map(|(_, trak)| trak)
+        .collect();
+    let chapter_ids: Vec<_> = traks
+        .iter()
+        .filter_map(|trak| child(child(trak, b"tref")?, b"chap"))
+        .flat_map(|chap| chap.chunks_exact(4))
+        .map(|id| u32::from_be_bytes(id.try_into().unwrap()))
+        .collect();
+    let Some(samples) = traks
+        .iter()
+        .filter(|trak| track_id(trak).is_some_and(|id| chapter_ids.contains(&id)))
+        .find_map(|trak| text_samples(trak))
+    else {
+        return Ok(Vec::new());
+    };
+
+    let mut file = File::open(path)?;
+    let mut chapters = Vec::new();
+    for (start, offset, size) in samples {
+        if !(2..=MAX_TITLE_SIZE).contains(&size) {
+            continue;
+        }
+        let mut sample = vec![0; size as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut sample)?;
+        let len = usize::from(u16::from_be_bytes([sample[0], sample[1]]));
+        let Some(text) = sample.get(2..2 + len) else {
+            continue;
+        };
+        chapters.push(Chapter {
+            start,
+            title: decode_text(text),
+        });
+    }
+    Ok(chapters)
+}
+
+/// The ID a track header gives its track.
+fn track_id(trak: &[u8]) -> Option<u32> {
+    let tkhd = child(trak, b"tkhd")?;
+    // Version 1 headers have 64-bit creation and modification times.
+    let pos = if *tkhd.first()? == 1 { 20 } else { 12 };
+    u32_at(tkhd, pos)
+}
+
+/// Where each sample of a track starts, in milliseconds, and where its data is in the file: the
+/// offset and size.
+fn text_samples(trak: &[u8]) -> Option<Vec<(f64, u64, u32)>> {
+    let mdia = child(trak, b"mdia")?;
+    let mdhd = child(mdia, b"mdhd")?;
+    let timescale = u32_at(mdhd, if *mdhd.first()? == 1 { 20 } else { 12 })?;
+    if timescale == 0 {
+        return None;
+    }
+    let stbl = [b"minf", b"stbl"]
+        .iter()
+        .try_fold(mdia, |body, name| child(body, name))?;
+
+    // Durations come as runs of samples with the same length.
+    let mut starts = Vec::new();
+    let mut time = 0u64;
+    for run in table(child(stbl, b"stts")?, 8)? {
+        let (count, delta) = (u32_at(run, 0)?, u32_at(run, 4)?);
+        for _ in 0..count.min(u32::from(u16::MAX)) {
+            starts.push(time as f64 * 1000.0 / f64::from(timescale));
+            time += u64::from(delta);
+        }
+    }
+
+    let stsz = child(stbl, b"stsz")?;
+    let (fixed, count) = (u32_at(stsz, 4)?, u32_at(stsz, 8)?);
+    let sizes: Vec<u32> = match fixed {
+        0 => (0..count as usize)
+            .map(|idx| u32_at(stsz, 12 + 4 * idx))
+            .collect::<Option<_>>()?,
+        size => vec![size; count.min(u32::from(u16::MAX)) as usize],
+    };
+
+    let offsets: Vec<u64> = match child(stbl, b"stco") {
+        Some(stco) => table(stco, 4)?
+            .map(|offset| u32_at(offset, 0).map(u64::from))
+            .collect::<Option<_>>()?,
+        None => table(child(stbl, b"co64")?, 8)?
+            .map(|offset| u64_at(offset, 0))
+            .collect::<Option<_>>()?,
+    };
+
+    // Chunks hold runs of samples laid end to end; each run of chunks shares a sample count.
+    let runs: Vec<(u32, u32)> = table(child(stbl, b"stsc")?, 12)?
+        .map(|run| Some((u32_at(run, 0)?, u32_at(run, 4)?)))
+        .collect::<Option<_>>()?;
+    let mut samples = Vec::new();
+    let mut sample = 0;
+    for (chunk, &chunk_offset) in offsets.iter().enumerate() {
+        let chunk = chunk as u32 + 1;
+        let per_chunk = runs
+            .iter()
+            .take_while(|(first, _)| *first <= chunk)
+            .last()
+            .map_or(0, |&(_, count)| count);
+        let mut offset = chunk_offset;
+        for _ in 0..per_chunk {
+            let (Some(&start), Some(&size)) = (starts.get(sample), sizes.get(sample)) else {
+                return Some(samples);
+            };
+            samples.push((start, offset, size));
+            offset += u64::from(size);
+            sample += 1;
+        }
+    }
+    Some(samples)
+}
+
+/// The entries of a full box that holds a count and then fixed-size entries, as the sample
+/// tables do.
+fn table(body: &[u8], entry_size: usize) -> Option<std::slice::ChunksExact<'_, u8>> {
+    let count = u32_at(body, 4)? as usize;
+    let entries = body.get(8..8 + count.checked_mul(entry_size)?)?;
+    Some(entries.chunks_exact(entry_size))
+}
+
+/// Text samples are UTF-8, or UTF-16 when they start with a byte order mark.
+fn decode_text(text: &[u8]) -> String {
+    let utf16 = |bytes: &[u8], decode: fn([u8; 2]) -> u16| {
+        let units: Vec<_> = bytes
+            .chunks_exact(2)
+            .map(|unit| decode([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    };
+    match text {
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        text => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        bytes.get(pos..pos + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn u64_at(bytes: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(
+        bytes.get(pos..pos + 8)?.try_into().unwrap(),
+    ))
+}
@@ -0,0 +1,299 @@
+use std::path::Path;
+
+use id3::TagLike;
+
+use crate::{
+    meta::{self, Meta},
+    Error, Result,
+};
+
+/// A file's tag in its own format, for fields that audiotags doesn't expose.
+pub enum NativeTag {
+    Id3(id3::Tag),
+    Mp4(mp4ameta::Tag),
+    Flac(metaflac::Tag),
+}
+
+impl NativeTag {
+    /// Reads the tag of a file in one of the formats we understand. Unknown formats and
+    /// unreadable tags give `None`; the main read reports those properly.
+    pub fn read(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "mp3" => id3::Tag::read_from_path(path).ok().map(NativeTag::Id3),
+            "m4a" | "m4b" | "m4p" | "m4v" | "isom" | "mp4" => {
+                mp4ameta::Tag::read_from_path(path).ok().map(NativeTag::Mp4)
+            }
+            "flac" => metaflac::Tag::read_from_path(path)
+                .ok()
+                .map(NativeTag::Flac),
+            _ => None,
+        }
+    }
+
+    /// Looks up a user-defined field by name, ignoring case: an ID3 `TXXX` frame, a Vorbis
+    /// comment, or an MP4 freeform (`----`) atom.
+    pub fn custom(&self, name: &str) -> Option<&str> {
+        match self {
+            NativeTag::Id3(tag) => tag
+                .extended_texts()
+                .find(|text| text.description.eq_ignore_ascii_case(name))
+                .map(|text| text.value.as_str()),
+            NativeTag::Mp4(tag) => tag.strings().find_map(|(ident, value)| match ident {
+                mp4ameta::DataIdent::Freeform { name: key, .. }
+                    if key.eq_ignore_ascii_case(name) =>
+                {
+                    Some(value)
+                }
+                _ => None,
+            }),
+            NativeTag::Flac(tag) => tag.vorbis_comments().and_then(|comments| {
+                comments
+                    .comments
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .and_then(|(_, values)| values.first())
+                    .map(String::as_str)
+            }),
+        }
+    }
+}
+
+impl NativeTag {
+    /// Fills in the work and movement of a classical track, which audiotags doesn't read: the
+    /// `TIT1`, `MVNM`, and `MVIN` frames iTunes writes to ID3 tags, the `©wrk`, `©mvn`, `©mvi`,
+    /// and `©mvc` atoms of MP4, or `WORK`, `MOVEMENTNAME`, `MOVEMENT`, and `MOVEMENTTOTAL`
+    /// comments.
+    pub fn read_work(&self, meta: &mut Meta) {
+        let text = |value: Option<&str>| value.and_then(meta::scrub);
+        match self {
+            NativeTag::Id3(tag) => {
+                meta.work = text(id3_text(tag, "TIT1").as_deref());
+                meta.movement = text(id3_text(tag, "MVNM").as_deref());
+                (meta.movement_number, meta.movement_count) =
+                    id3_text(tag, "MVIN").map_or((None, None), |index| pair(&index));
+            }
+            NativeTag::Mp4(tag) => {
+                meta.work = text(tag.work());
+                meta.movement = text(tag.movement());
+                meta.movement_number = tag.movement_index();
+                meta.movement_count = tag.movement_count();
+            }
+            NativeTag::Flac(_) => {
+                meta.work = text(self.custom("WORK"));
+                meta.movement = text(self.custom("MOVEMENTNAME"));
+                let (number, count) = self.custom("MOVEMENT").map_or((None, None), pair);
+                meta.movement_number = number;
+                meta.movement_count = self
+                    .custom("MOVEMENTTOTAL")
+                    .and_then(|count| count.trim().parse().ok())
+                    .or(count);
+            }
+        }
+
+        // Some taggers write zero for "not part of a work".
+        meta.movement_number = meta.movement_number.filter(|&n| n > 0);
+        meta.movement_count = meta.movement_count.filter(|&n| n > 0);
+    }
+}
+
+/// The text of an ID3 frame. The id3 crate only decodes frames it knows as text, and iTunes'
+/// movement frames aren't among them.
+fn id3_text(tag: &id3::Tag, id: &str) -> Option<String> {
+    let content = tag.get(id)?.content();
+    if let Some(text) = content.text() {
+        return Some(text.into());
+    }
+
+    let unknown = content.to_unknown().ok()?;
+    let (&encoding, data) = unknown.data.split_first()?;
+    let text = match encoding {
+        0 => data.iter().map(|&b| char::from(b)).collect(),
+        1 | 2 => {
+            let big_endian = match data {
+                [0xff, 0xfe, ..] => false,
+                [0xfe, 0xff, ..] => true,
+                _ => encoding == 2,
+            };
+            let body = data
+                .strip_prefix(&[0xff, 0xfe])
+                .or(data.strip_prefix(&[0xfe, 0xff]))
+                .unwrap_or(data);
+            let units: Vec<_> = body
+                .chunks_exact(2)
+                .map(|unit| match big_endian {
+                    true => u16::from_be_bytes([unit[0], unit[1]]),
+                    false => u16::from_le_bytes([unit[0], unit[1]]),
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(data).into_owned(),
+    };
+    Some(text.trim_end_matches('\0').to_owned())
+}
+
+/// Splits a value like `2/4` into a number and a total.
+fn pair(value: &str) -> (Option<u16>, Option<u16>) {
+    let (number, total) = value.split_once('/').unwrap_or((value, ""));
+    (number.trim().parse().ok(), total.trim().parse().ok())
+}
+
+/// Song lyrics embedded in a file.
+pub enum Lyrics {
+    /// Lines with the time in milliseconds at which each starts.
+    Synced(Vec<(u32, String)>),
+    Plain(String),
+}
+
+impl NativeTag {
+    /// The file's lyrics, preferring timed ones: an ID3 `SYLT` or `USLT` frame, an MP4 `©lyr`
+    /// atom, or a `LYRICS` or `UNSYNCEDLYRICS` Vorbis comment.
+    pub fn lyrics(&self) -> Option<Lyrics> {
+        let plain = |text: &str| {
+            let text = text.trim();
+            (!text.is_empty()).then(|| Lyrics::Plain(text.to_owned()))
+        };
+
+        match self {
+            NativeTag::Id3(tag) => tag
+                .synchronised_lyrics()
+                .find(|lyrics| lyrics.timestamp_format == id3::frame::TimestampFormat::Ms)
+                .map(|lyrics| Lyrics::Synced(lyrics.content.clone()))
+                .or_else(|| tag.lyrics().find_map(|lyrics| plain(&lyrics.text))),
+            NativeTag::Mp4(tag) => tag.lyrics().and_then(plain),
+            NativeTag::Flac(_) => ["LYRICS", "UNSYNCEDLYRICS"]
+                .iter()
+                .find_map(|name| self.custom(name).and_then(plain)),
+        }
+    }
+}
+
+/// Writes the tags set in `meta` into a file, leaving its other tags alone. Files without a tag
+/// get one.
+pub fn write(path: &Path, meta: &Meta) -> Result<()> {
+    let failed = |e: &dyn std::fmt::Display| Error::Retag(e.to_string());
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "mp3" => {
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(failed(&e)),
+            };
+            if let Some(v) = &meta.album {
+                tag.set_album(v);
+            }
+            if let Some(v) = &meta.album_artist {
+                tag.set_album_artist(v);
+            }
+            if let Some(v) = &meta.artist {
+                tag.set_artist(v);
+            }
+            if let Some(v) = &meta.title {
+                tag.set_title(v);
+            }
+            if let Some(v) = meta.track {
+                tag.set_track(v.into());
+            }
+            if let Some(v) = meta.disc {
+                tag.set_disc(v.into());
+            }
+            if let Some(v) = meta.year {
+                tag.set_year(v);
+            }
+            tag.write_to_path(path, id3::Version::Id3v24)
+                .map_err(|e| failed(&e))
+        }
+        "m4a" | "m4b" | "m4p" | "m4v" | "isom" | "mp4" => {
+            let mut tag = mp4ameta::Tag::read_from_path(path).map_err(|e| failed(&e))?;
+            if let Some(v) = &meta.album {
+                tag.set_album(v);
+            }
+            if let Some(v) = &meta.album_artist {
+                tag.set_album_artist(v);
+            }
+            if let Some(v) = &meta.artist {
+                tag.set_artist(v);
+            }
+            if let Some(v) = &meta.title {
+                tag.set_title(v);
+            }
+            if let Some(v) = meta.track {
+                tag.set_track_number(v);
+            }
+            if let Some(v) = meta.disc {
+                tag.set_disc_number(v);
+            }
+            if let Some(v) = meta.year {
+                tag.set_year(v.to_string());
+            }
+            tag.write_to_path(path).map_err(|e| failed(&e))
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| failed(&e))?;
+            let fields = [
+                ("ALBUM", meta.album.clone()),
+                ("ALBUMARTIST", meta.album_artist.clone()),
+                ("ARTIST", meta.artist.clone()),
+                ("TITLE", meta.title.clone()),
+                ("TRACKNUMBER", meta.track.map(|v| v.to_string())),
+                ("DISCNUMBER", meta.disc.map(|v| v.to_string())),
+                ("DATE", meta.year.map(|v| v.to_string())),
+            ];
+            for (key, value) in fields {
+                if let Some(value) = value {
+                    tag.set_vorbis(key, vec![value]);
+                }
+            }
+            tag.save().map_err(|e| failed(&e))
+        }
+        _ => Err(Error::Retag(format!(
+            "can't write tags to .{extension} files"
+        ))),
+    }
+}
+
+/// Sets a user-defined field by name: an ID3 `TXXX` frame, an MP4 freeform atom under the
+/// `com.apple.iTunes` namespace, or a Vorbis comment.
+pub fn write_custom(path: &Path, name: &str, value: &str) -> Result<()> {
+    let failed = |e: &dyn std::fmt::Display| Error::Retag(e.to_string());
+    let extension = meta::format_extension(path).unwrap_or_default();
+
+    match extension.as_str() {
+        "mp3" => {
+            let mut tag = match id3::Tag::read_from_path(path) {
+                Ok(tag) => tag,
+                Err(e) if matches!(e.kind, id3::ErrorKind::NoTag) => id3::Tag::new(),
+                Err(e) => return Err(failed(&e)),
+            };
+            tag.add_frame(id3::frame::ExtendedText {
+                description: name.into(),
+                value: value.into(),
+            });
+            tag.write_to_path(path, id3::Version::Id3v24)
+                .map_err(|e| failed(&e))
+        }
+        "m4a" | "m4b" | "m4p" | "m4v" | "isom" | "mp4" => {
+            let mut tag = mp4ameta::Tag::read_from_path(path).map_err(|e| failed(&e))?;
+            tag.set_data(
+                mp4ameta::FreeformIdent::new("com.apple.iTunes", name),
+                mp4ameta::Data::Utf8(value.into()),
+            );
+            tag.write_to_path(path).map_err(|e| failed(&e))
+        }
+        "flac" => {
+            let mut tag = metaflac::Tag::read_from_path(path).map_err(|e| failed(&e))?;
+            tag.set_vorbis(name, vec![value]);
+            tag.save().map_err(|e| failed(&e))
+        }
+        _ => Err(Error::Retag(format!(
+            "can't write tags to .{extension} files"
+        ))),
+    }
+}
@@ -0,0 +1,129 @@
+use std::{io, process};
+
+use crate::{config::Config, http, Error, Result};
+
+/// Something to tell about changed files, so that it picks them up without a manual rescan.
+#[derive(Debug, Clone)]
+enum Notifier {
+    /// Refresh a Plex library section, or every section.
+    Plex {
+        url: String,
+        token: String,
+        section: Option<String>,
+    },
+    /// Refresh every Jellyfin library.
+    Jellyfin { url: String, api_key: String },
+    /// Run a shell command, such as `mpc update`.
+    Command(String),
+}
+
+/// The notifiers configured in the `[notify]` section of the config file:
+///
+/// ```toml
+/// [notify]
+/// mpd = true                  # runs `mpc update`
+/// commands = ["beet update"]
+///
+/// [notify.plex]
+/// url = "http://localhost:32400"
+/// token = "..."
+/// section = "1"               # optional; all sections otherwise
+///
+/// [notify.jellyfin]
+/// url = "http://localhost:8096"
+/// api_key = "..."
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Notifiers {
+    notifiers: Vec<Notifier>,
+}
+
+impl Notifiers {
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut notifiers = Vec::new();
+        let required = |key: &str| {
+            config
+                .string(key)?
+                .map(str::to_owned)
+                .ok_or_else(|| Error::Config(format!("{key}: required")))
+        };
+
+        if config.string("notify.plex.url")?.is_some() {
+            notifiers.push(Notifier::Plex {
+                url: required("notify.plex.url")?,
+                token: required("notify.plex.token")?,
+                section: config.string("notify.plex.section")?.map(str::to_owned),
+            });
+        }
+        if config.string("notify.jellyfin.url")?.is_some() {
+            notifiers.push(Notifier::Jellyfin {
+                url: required("notify.jellyfin.url")?,
+                api_key: required("notify.jellyfin.api_key")?,
+            });
+        }
+        if config.bool("notify.mpd")? {
+            notifiers.push(Notifier::Command("mpc update".into()));
+        }
+        for command in config.strings("notify.commands")? {
+            notifiers.push(Notifier::Command(command.into()));
+        }
+
+        Ok(Self { notifiers })
+    }
+
+    /// Notifies everything configured. Failures are reported but don't fail the run; the files
+    /// have already been renamed by the time anyone is told.
+    pub fn run(&self) {
+        for notifier in &self.notifiers {
+            let (name, result) = match notifier {
+                Notifier::Plex {
+                    url,
+                    token,
+                    section,
+                } => {
+                    let section = section.as_deref().unwrap_or("all");
+                    let url = format!(
+                        "{}/library/sections/{section}/refresh",
+                        url.trim_end_matches('/')
+                    );
+                    ("plex", request("GET", &url, &[("X-Plex-Token", token)]))
+                }
+                Notifier::Jellyfin { url, api_key } => {
+                    let url = format!("{}/Library/Refresh", url.trim_end_matches('/'));
+                    (
+                        "jellyfin",
+                        request("POST", &url, &[("X-Emby-Token", api_key)]),
+                    )
+                }
+                Notifier::Command(command) => (command.as_str(), shell(command)),
+            };
+
+            if let Err(e) = result {
+                eprintln!("{name}: notification failed: {e}");
+            }
+        }
+    }
+}
+
+fn request(method: &str, url: &str, headers: &[(&str, &str)]) -> io::Result<()> {
+    match http::send(method, url, headers, &[])? {
+        200..=299 => Ok(()),
+        status => Err(io::Error::other(format!("server answered {status}"))),
+    }
+}
+
+fn shell(command: &str) -> io::Result<()> {
+    let status = if cfg!(windows) {
+        process::Command::new("cmd")
+            .args(["/C", command])
+            .status()?
+    } else {
+        process::Command::new("sh").args(["-c", command]).status()?
+    };
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(status.to_string()))
+    }
+}
@@ -0,0 +1,377 @@
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+
+use crate::{
+    diff, http, json,
+    plan::{Entry, Status},
+    walk,
+};
+
+/// Where the output goes.
+#[derive(Debug, Clone)]
+pub enum Sink {
+    Stdout,
+    File(PathBuf),
+
+    /// An `http://` URL the output is POSTed to.
+    Url(String),
+}
+
+impl Sink {
+    /// Delivers the whole output, described by `content_type` for the benefit of web services.
+    /// Output for stdout is expected to have been written there already.
+    pub fn deliver(&self, output: &[u8], content_type: &str) -> io::Result<()> {
+        match self {
+            Sink::Stdout => Ok(()),
+            Sink::File(path) => fs::write(path, output),
+            Sink::Url(url) => {
+                let status = http::send("POST", url, &[("Content-Type", content_type)], output)?;
+                if !(200..300).contains(&status) {
+                    return Err(io::Error::other(format!("{url} answered with {status}")));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses `-` for stdout, an `http://` URL, or a file name.
+pub fn parse_sink(s: &str) -> Result<Sink, String> {
+    match s {
+        "-" => Ok(Sink::Stdout),
+        s if s.starts_with("http://") => Ok(Sink::Url(s.into())),
+        s if s.contains("://") => Err("only http:// URLs are supported".into()),
+        s => Ok(Sink::File(s.into())),
+    }
+}
+
+/// Prints the plan for people: proposed names on stdout, problems on stderr. With `diff`, files
+/// that collide are compared with what they collide with. With `verbose`, each line starts with
+/// the entry's number, as `--indices` takes it, and proposed names end with their lengths.
+pub fn text(
+    out: &mut dyn Write,
+    entries: &[Entry],
+    dry_run: bool,
+    diff: bool,
+    verbose: bool,
+) -> io::Result<()> {
+    let cwd = env::current_dir().unwrap_or_default();
+    for (idx, entry) in entries.iter().enumerate() {
+        let n = if verbose {
+            format!("{:>4}  ", idx + 1)
+        } else {
+            String::new()
+        };
+        let lengths = match &entry.target {
+            Some(target) if verbose => lengths(&cwd.join(target)),
+            _ => String::new(),
+        };
+        for note in &entry.notes {
+            eprintln!("{}: {note}", entry.source.display());
+        }
+
+        let target = entry.target.as_deref().map(Path::display);
+        match (entry.status, target) {
+            (Status::Renamed | Status::Copied | Status::Unchanged, Some(target)) if dry_run => {
+                writeln!(out, "{n}{target}{lengths}")?
+            }
+            (Status::SkippedExisting, Some(target)) if dry_run => writeln!(
+                out,
+                "{n}{target} (would conflict with existing file){lengths}"
+            )?,
+            (Status::SkippedExisting, Some(target)) => eprintln!(
+                "{n}{}: skipped, {target} already exists",
+                entry.source.display()
+            ),
+            (Status::Conflict, Some(target)) if dry_run => writeln!(
+                out,
+                "{n}{target} (conflicts with another file in this run){lengths}"
+            )?,
+            (Status::Conflict, Some(target)) => eprintln!(
+                "{n}{}: skipped, another file would also be named {target}",
+                entry.source.display()
+            ),
+            (Status::Hardlinked, _) if dry_run => writeln!(
+                out,
+                "{n}{} (would be replaced by a hard link to {})",
+                entry.source.display(),
+                entry.duplicate.as_deref().unwrap().display()
+            )?,
+            (Status::SkippedDuplicate, _) => {
+                let duplicate = entry.duplicate.as_deref().unwrap().display();
+                if dry_run {
+                    writeln!(out, "{n}{duplicate} (already exists with the same content)")?;
+                } else {
+                    eprintln!(
+                        "{n}{}: skipped, same content as {duplicate}",
+                        entry.source.display()
+                    );
+                }
+            }
+            _ => {
+                if let Some(e) = &entry.error {
+                    eprintln!("{n}{}: {e}", entry.source.display());
+                }
+            }
+        }
+
+        if diff {
+            let other = match entry.status {
+                Status::SkippedExisting => entry.target.clone(),
+                Status::Conflict => entries
+                    .iter()
+                    .enumerate()
+                    .find(|(other, e)| *other != idx && e.target == entry.target)
+                    .map(|(_, e)| e.source.clone()),
+                _ => None,
+            };
+            if let Some(other) = other {
+                diff::print(&entry.source, &other);
+            }
+        }
+    }
+
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+    let unsupported = count(Status::UnsupportedFormat);
+    if unsupported > 0 {
+        eprintln!("{unsupported} files in formats that can't be read yet were left alone");
+    }
+    let drm = count(Status::SkippedDrm);
+    if drm > 0 {
+        eprintln!("{drm} DRM-protected files were left alone");
+    }
+    let partial = count(Status::SkippedPartial);
+    if partial > 0 {
+        eprintln!("{partial} unfinished downloads were left alone");
+    }
+    Ok(())
+}
+
+/// The longest file name most filesystems allow, in bytes.
+const NAME_LIMIT: usize = 255;
+
+/// The longest path Windows allows without long path support, in characters.
+const PATH_LIMIT: usize = 260;
+
+/// How close to a limit a length has to be to be worth a warning.
+const NEAR_LIMIT: usize = 20;
+
+/// The byte length of a target's name and the length of its full path, with warnings for either
+/// being at or near the limits of stricter filesystems.
+fn lengths(target: &Path) -> String {
+    let name = target.file_name().map_or(0, |name| name.len());
+    let path = walk::normalize(target).to_string_lossy().chars().count();
+
+    let mut out = format!("  [name {name} bytes, full path {path} characters");
+    if name > NAME_LIMIT {
+        out.push_str(&format!("; name longer than {NAME_LIMIT} bytes"));
+    } else if name + NEAR_LIMIT > NAME_LIMIT {
+        out.push_str("; name near the limit");
+    }
+    if path > PATH_LIMIT {
+        out.push_str(&format!("; path longer than {PATH_LIMIT} characters"));
+    } else if path + NEAR_LIMIT > PATH_LIMIT {
+        out.push_str("; path near the Windows limit");
+    }
+    out.push(']');
+    out
+}
+
+/// Prints the plan as a single JSON document.
+///
+/// Each file carries a status from a fixed set and, when something went wrong, an error object
+/// with a machine-readable kind alongside the human-readable message.
+pub fn json(out: &mut dyn Write, entries: &[Entry], dry_run: bool) -> io::Result<()> {
+    let mut doc = String::new();
+    doc.push_str("{\"dry_run\":");
+    doc.push_str(if dry_run { "true" } else { "false" });
+    doc.push_str(",\"files\":[");
+
+    for (idx, entry) in entries.iter().enumerate() {
+        if idx > 0 {
+            doc.push(',');
+        }
+
+        doc.push_str("{\"source\":");
+        json::write_str(&mut doc, &entry.source.to_string_lossy());
+        doc.push_str(",\"target\":");
+        match &entry.target {
+            Some(target) => json::write_str(&mut doc, &target.to_string_lossy()),
+            None => doc.push_str("null"),
+        }
+        doc.push_str(",\"status\":");
+        json::write_str(&mut doc, entry.status.as_str());
+        if let Some(duplicate) = &entry.duplicate {
+            doc.push_str(",\"duplicate\":");
+            json::write_str(&mut doc, &duplicate.to_string_lossy());
+        }
+        if !entry.notes.is_empty() {
+            doc.push_str(",\"notes\":[");
+            for (idx, note) in entry.notes.iter().enumerate() {
+                if idx > 0 {
+                    doc.push(',');
+                }
+                json::write_str(&mut doc, note);
+            }
+            doc.push(']');
+        }
+        doc.push_str(",\"error\":");
+        match &entry.error {
+            Some(e) => {
+                doc.push_str("{\"kind\":");
+                json::write_str(&mut doc, e.kind());
+                doc.push_str(",\"message\":");
+                json::write_str(&mut doc, &e.to_string());
+                doc.push('}');
+            }
+            None => doc.push_str("null"),
+        }
+        doc.push('}');
+    }
+
+    doc.push_str("]}");
+    writeln!(out, "{doc}")
+}
+
+/// Prints the plan as a Markdown table, for pasting into a review.
+pub fn markdown(out: &mut dyn Write, entries: &[Entry]) -> io::Result<()> {
+    writeln!(out, "| Old | New | Status |")?;
+    writeln!(out, "| --- | --- | --- |")?;
+    for entry in entries {
+        let target = entry
+            .target
+            .as_deref()
+            .map(|target| cell(&target.to_string_lossy()))
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "| {} | {target} | {} |",
+            cell(&entry.source.to_string_lossy()),
+            entry.status.as_str()
+        )?;
+    }
+    Ok(())
+}
+
+/// Escapes text for a table cell, in code spans so that names with `*` or `_` come through
+/// literally.
+fn cell(s: &str) -> String {
+    let s = s.replace('|', "\\|");
+    if s.contains('`') {
+        format!("`` {s} ``")
+    } else {
+        format!("`{s}`")
+    }
+}
+
+/// The shell a script is written for.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Sh,
+    Powershell,
+}
+
+impl Shell {
+    fn quote(self, path: &Path) -> String {
+        let path = path.to_string_lossy();
+        match self {
+            Shell::Sh => format!("'{}'", path.replace('\'', "'\\''")),
+            Shell::Powershell => format!("'{}'", path.replace('\'', "''")),
+        }
+    }
+}
+
+/// Prints a script that carries out the plan, one command per file.
+///
+/// Each command ends with a comment giving the file's original name, so the script reads as a
+/// list of intended changes. Files the plan leaves alone appear as comments. Without `on_disk`,
+/// as for a catalog, every target directory gets a command to create it, since there's no telling
+/// which exist.
+pub fn script(
+    out: &mut dyn Write,
+    entries: &[Entry],
+    shell: Shell,
+    on_disk: bool,
+) -> io::Result<()> {
+    match shell {
+        Shell::Sh => writeln!(out, "#!/bin/sh\nset -e")?,
+        Shell::Powershell => writeln!(out, "$ErrorActionPreference = 'Stop'")?,
+    }
+
+    let mut created = HashSet::new();
+    for entry in entries {
+        let source = shell.quote(&entry.source);
+        let original = comment(entry.source.file_name().unwrap_or_default());
+        let target = entry.target.as_deref();
+
+        if let (true, Some(dir)) = (entry.status.is_change(), target.and_then(Path::parent)) {
+            let missing = !on_disk || !dir.exists();
+            if !dir.as_os_str().is_empty() && missing && created.insert(dir) {
+                let dir = shell.quote(dir);
+                match shell {
+                    Shell::Sh => writeln!(out, "mkdir -p -- {dir}")?,
+                    Shell::Powershell => writeln!(
+                        out,
+                        "New-Item -ItemType Directory -Force -Path {dir} | Out-Null"
+                    )?,
+                }
+            }
+        }
+
+        match (entry.status, target, shell) {
+            (Status::Renamed, Some(target), Shell::Sh) => writeln!(
+                out,
+                "mv -n -- {source} {}  # {original}",
+                shell.quote(target)
+            )?,
+            (Status::Renamed, Some(target), Shell::Powershell) => writeln!(
+                out,
+                "Move-Item -LiteralPath {source} -Destination {}  # {original}",
+                shell.quote(target)
+            )?,
+            (Status::Copied, Some(target), Shell::Sh) => writeln!(
+                out,
+                "cp -n -- {source} {}  # {original}",
+                shell.quote(target)
+            )?,
+            (Status::Copied, Some(target), Shell::Powershell) => writeln!(
+                out,
+                "Copy-Item -LiteralPath {source} -Destination {}  # {original}",
+                shell.quote(target)
+            )?,
+            (Status::Hardlinked, _, _) => {
+                let duplicate = shell.quote(entry.duplicate.as_deref().unwrap());
+                match shell {
+                    Shell::Sh => writeln!(out, "ln -f -- {duplicate} {source}  # {original}")?,
+                    Shell::Powershell => writeln!(out,
+                        "New-Item -ItemType HardLink -Force -Path {source} -Target {duplicate} | Out-Null  # {original}"
+                    )?,
+                }
+            }
+            (Status::Unchanged, _, _) => {}
+            (status, _, _) => writeln!(
+                out,
+                "# {}: {}",
+                status.as_str(),
+                comment(entry.source.as_os_str())
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// A name made safe to end a line with.
+fn comment(name: &OsStr) -> String {
+    name.to_string_lossy()
+        .chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .collect()
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::{format::Tag, meta::Meta, Error, Result};
+
+/// A template run backwards: `%track - %artist - %title` reads those tags out of a file name.
+///
+/// Each `/` in the pattern takes in one more directory, so `%artist/%album/%track %title`
+/// matches against the file's last three path components.
+#[derive(Debug, Clone)]
+pub struct InputPattern {
+    rx: Regex,
+    tags: Vec<Tag>,
+    depth: usize,
+}
+
+impl InputPattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let tokens = Regex::new(r"%([a-z]+)|([^%]+)").unwrap();
+        let mut rx = String::from("^");
+        let mut tags = Vec::new();
+
+        for cx in tokens.captures_iter(pattern) {
+            let Some(key) = cx.get(1) else {
+                rx += &regex::escape(cx.get(2).unwrap().as_str());
+                continue;
+            };
+
+            let tag: Tag = key.as_str().parse()?;
+            rx += match tag {
+                Tag::Track | Tag::Disc | Tag::Year => r"(\d+)",
+                Tag::Album | Tag::AlbumArtist | Tag::Artist | Tag::Title => "(.+?)",
+                _ => {
+                    return Err(Error::Format(format!(
+                        "{} can't be read from a name",
+                        key.as_str()
+                    )))
+                }
+            };
+            tags.push(tag);
+        }
+        rx.push('$');
+
+        Ok(Self {
+            rx: Regex::new(&rx).map_err(|e| Error::Format(e.to_string()))?,
+            tags,
+            depth: pattern.matches('/').count() + 1,
+        })
+    }
+
+    /// Fills in the tags `meta` is missing from the file's name.
+    ///
+    /// Returns the tags that were filled in, holding only those values, or `None` if the name
+    /// doesn't match or supplies nothing new.
+    pub fn fill(&self, path: &Path, meta: &mut Meta) -> Option<(Meta, Vec<Tag>)> {
+        let mut parts: Vec<_> = path
+            .with_extension("")
+            .components()
+            .rev()
+            .take(self.depth)
+            .map(|part| part.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        parts.reverse();
+        let name = parts.join("/");
+        let cx = self.rx.captures(&name)?;
+
+        let mut filled = Meta::default();
+        let mut tags = Vec::new();
+        for (idx, &tag) in self.tags.iter().enumerate() {
+            let value = cx.get(idx + 1).unwrap().as_str().trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            let text = |field: &mut Option<String>, copy: &mut Option<String>| {
+                if field.is_none() {
+                    *field = Some(value.to_owned());
+                    *copy = field.clone();
+                    true
+                } else {
+                    false
+                }
+            };
+            let number = |field: &mut Option<u16>, copy: &mut Option<u16>| {
+                if field.is_none() {
+                    *field = value.parse().ok();
+                    *copy = *field;
+                }
+                copy.is_some()
+            };
+
+            let changed = match tag {
+                Tag::Album => text(&mut meta.album, &mut filled.album),
+                Tag::AlbumArtist => text(&mut meta.album_artist, &mut filled.album_artist),
+                Tag::Artist => text(&mut meta.artist, &mut filled.artist),
+                Tag::Title => text(&mut meta.title, &mut filled.title),
+                Tag::Track => number(&mut meta.track, &mut filled.track),
+                Tag::Disc => number(&mut meta.disc, &mut filled.disc),
+                Tag::Year if meta.year.is_none() => {
+                    meta.year = value.parse().ok();
+                    filled.year = meta.year;
+                    filled.year.is_some()
+                }
+                _ => false,
+            };
+            if changed {
+                tags.push(tag);
+            }
+        }
+
+        (!tags.is_empty()).then_some((filled, tags))
+    }
+}
@@ -0,0 +1,751 @@
+use clap::ValueEnum;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    album::Albums,
+    dedupe::{same_content, Library},
+    meta::{self, Meta},
+    pattern::InputPattern,
+    readonly, walk, Error, Format, Result,
+};
+
+/// Where renamed files end up.
+#[derive(Debug, Clone)]
+pub enum Mode {
+    /// Rename files in place, relative to their own directories.
+    Rename,
+    /// Move files into a library directory.
+    Move(PathBuf),
+    /// Copy files into a library directory, leaving the originals alone.
+    Copy(PathBuf),
+}
+
+impl Mode {
+    pub fn destination(&self) -> Option<&Path> {
+        match self {
+            Mode::Rename => None,
+            Mode::Move(root) | Mode::Copy(root) => Some(root),
+        }
+    }
+
+    /// The status of an entry this mode puts at a new path.
+    pub fn change_status(&self) -> Status {
+        match self {
+            Mode::Copy(_) => Status::Copied,
+            Mode::Rename | Mode::Move(_) => Status::Renamed,
+        }
+    }
+}
+
+/// What to do when a file's target is taken, by a file on disk or by another file in the run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnConflict {
+    /// Leave the file alone and report the collision.
+    #[default]
+    Skip,
+    /// Keep both, giving the newcomer a numbered name: `name (2).ext`.
+    Suffix,
+}
+
+/// What to do when a file's content already exists at its target or in the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Dedupe {
+    /// Leave the file where it is and report the duplicate.
+    Skip,
+    /// Replace the file with a hard link to the existing copy.
+    Hardlink,
+}
+
+/// The order files are planned in, which decides which of two colliding files keeps the name and
+/// how previews list them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortInput {
+    /// By path.
+    Path,
+    /// Oldest modification time first.
+    Mtime,
+    /// By album, then disc and track number; files whose tags can't be read go last.
+    Track,
+}
+
+/// What to do with albums whose folders have no cover art.
+#[derive(Debug, Clone)]
+pub enum MissingArt {
+    /// Append this to the name of the album's folder.
+    Marker(String),
+    /// Put the album under this directory instead of its usual destination.
+    Review(PathBuf),
+}
+
+/// Settings that shape the plan.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub mode: Mode,
+
+    /// Duplicate handling. Imports into a destination always skip duplicates unless told to
+    /// hard link them; in-place renames only look for duplicates when asked.
+    pub dedupe: Option<Dedupe>,
+
+    /// Salvage partial or guessed tags from files whose tags fail to parse.
+    pub lenient: bool,
+
+    /// Fills in missing tags from file names.
+    pub input_pattern: Option<InputPattern>,
+
+    /// How long to spend reading one file's tags before giving up on it.
+    pub timeout: Option<Duration>,
+
+    /// Flags albums without cover art so that they're easy to find afterwards.
+    pub missing_art: Option<MissingArt>,
+
+    /// Directories owned by something else, such as another library manager, whose files must
+    /// not be renamed, replaced, or added to.
+    pub protected: Vec<PathBuf>,
+
+    /// Settles collisions instead of reporting them.
+    pub on_conflict: OnConflict,
+
+    /// Reorders files before planning. Without it, files keep the order they were found in.
+    pub sort: Option<SortInput>,
+
+    /// Tags from an exported catalog, used in place of reading the files.
+    pub catalog: Option<HashMap<PathBuf, Meta>>,
+
+    /// Read-only sources are to be made writable for the rename instead of being skipped.
+    pub clear_readonly: bool,
+
+    /// The most directories and file name a generated name may have.
+    pub max_depth: usize,
+}
+
+/// What happened (or, in a preview, what would happen) to a file.
+///
+/// The string forms are part of the JSON output and must stay stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Renamed,
+    Copied,
+    Unchanged,
+    SkippedExisting,
+    SkippedDuplicate,
+    Hardlinked,
+    SkippedMissingTag,
+    SkippedProtected,
+    SkippedReadOnly,
+    TooDeep,
+    UnsafeName,
+    SkippedDrm,
+    SkippedPartial,
+    SkippedByUser,
+    UnsupportedFormat,
+    Conflict,
+    IoError,
+    Interrupted,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Renamed => "renamed",
+            Status::Copied => "copied",
+            Status::Unchanged => "unchanged",
+            Status::SkippedExisting => "skipped_existing",
+            Status::SkippedDuplicate => "skipped_duplicate",
+            Status::Hardlinked => "hardlinked",
+            Status::SkippedMissingTag => "skipped_missing_tag",
+            Status::SkippedProtected => "skipped_protected",
+            Status::SkippedReadOnly => "skipped_read_only",
+            Status::TooDeep => "too_deep",
+            Status::UnsafeName => "unsafe_name",
+            Status::SkippedDrm => "skipped_drm",
+            Status::SkippedPartial => "skipped_partial",
+            Status::SkippedByUser => "skipped_by_user",
+            Status::UnsupportedFormat => "unsupported_format",
+            Status::Conflict => "conflict",
+            Status::IoError => "io_error",
+            Status::Interrupted => "interrupted",
+        }
+    }
+
+    /// True for entries that put a file at a new path.
+    pub fn is_change(self) -> bool {
+        matches!(self, Status::Renamed | Status::Copied)
+    }
+
+    /// True for outcomes that should make the run exit unsuccessfully.
+    pub fn is_failure(self) -> bool {
+        matches!(
+            self,
+            Status::SkippedMissingTag
+                | Status::TooDeep
+                | Status::UnsafeName
+                | Status::IoError
+                | Status::Interrupted
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub source: PathBuf,
+    pub target: Option<PathBuf>,
+    pub status: Status,
+    pub error: Option<Error>,
+
+    /// A file in the destination that already has this file's content.
+    pub duplicate: Option<PathBuf>,
+
+    /// The album the file was grouped into, for reports.
+    pub album: Option<String>,
+
+    /// Tags taken from the file name by an input pattern, to be written back with `--retag`.
+    pub inferred: Option<Meta>,
+
+    /// The tags the file was planned from, for exporting a catalog.
+    pub tags: Option<Meta>,
+
+    /// Things worth mentioning about a file that don't change its status.
+    pub notes: Vec<String>,
+}
+
+impl Entry {
+    fn failed(source: PathBuf, error: Error) -> Self {
+        let status = match error {
+            Error::MissingTag(_) | Error::Movements(_) | Error::NoAlbumArtist => {
+                Status::SkippedMissingTag
+            }
+            Error::DrmProtected => Status::SkippedDrm,
+            Error::Partial => Status::SkippedPartial,
+            Error::TooDeep(..) => Status::TooDeep,
+            Error::UnsafeName(_) => Status::UnsafeName,
+            Error::UnsupportedFormat(_) => Status::UnsupportedFormat,
+            _ => Status::IoError,
+        };
+
+        Self {
+            source,
+            target: None,
+            status,
+            error: Some(error),
+            duplicate: None,
+            album: None,
+            inferred: None,
+            tags: None,
+            notes: Vec::new(),
+        }
+    }
+
+    /// Records the result of carrying out a planned rename.
+    pub fn complete(&mut self, result: Result<()>) {
+        if let Err(e) = result {
+            self.status = Status::IoError;
+            self.error = Some(e);
+        }
+    }
+}
+
+/// Reads every path and works out its new name.
+///
+/// Files that can't be read or named are kept in the plan with a failing status, so that one bad
+/// file doesn't hide what would happen to the rest.
+pub fn build(format: &Format, paths: &[PathBuf], options: &Options) -> Result<Vec<Entry>> {
+    let mode = &options.mode;
+    let tracks: Vec<_> = paths
+        .iter()
+        .map(|path| {
+            let path = path.clone();
+            let mut notes = Vec::new();
+            let read = match &options.catalog {
+                Some(catalog) => catalog
+                    .get(&path)
+                    .cloned()
+                    .ok_or_else(|| Error::Catalog(format!("no tags for {}", path.display()))),
+                None => read(&path, options.timeout),
+            };
+            let mut meta = match read {
+                Err(e @ Error::AudioTags(_)) if options.lenient => {
+                    let (meta, how) = Meta::recover(&path);
+                    notes.push(format!("{e}; {how}"));
+                    Ok(meta)
+                }
+                meta => meta,
+            };
+
+            let mut inferred = None;
+            if let Some(pattern) = &options.input_pattern {
+                // A file with no tags at all can still be named from its file name.
+                let mut base = match &meta {
+                    Ok(meta) => Some(meta.clone()),
+                    Err(Error::AudioTags(_)) => Some(Meta::default()),
+                    Err(_) => None,
+                };
+                if let Some((filled, tags)) =
+                    base.as_mut().and_then(|base| pattern.fill(&path, base))
+                {
+                    let tags: Vec<_> = tags.iter().map(ToString::to_string).collect();
+                    notes.push(format!("took {} from the file name", tags.join(", ")));
+                    meta = Ok(base.unwrap());
+                    inferred = Some(filled);
+                }
+            }
+            (path, meta, notes, inferred)
+        })
+        .collect::<Vec<_>>();
+
+    // Sorts are stable, so ties keep the order files were found in.
+    let mut tracks = tracks;
+    match options.sort {
+        Some(SortInput::Path) => tracks.sort_by(|a, b| a.0.cmp(&b.0)),
+        Some(SortInput::Mtime) => tracks
+            .sort_by_cached_key(|(path, ..)| fs::metadata(path).and_then(|m| m.modified()).ok()),
+        Some(SortInput::Track) => tracks.sort_by_cached_key(|(path, meta, ..)| match meta {
+            Ok(meta) => (
+                false,
+                meta.album.clone(),
+                meta.disc,
+                meta.track,
+                path.clone(),
+            ),
+            Err(_) => (true, None, None, None, path.clone()),
+        }),
+        None => {}
+    }
+
+    let readable: Vec<_> = tracks
+        .iter()
+        .filter_map(|(path, meta, ..)| Some((path.as_path(), meta.as_ref().ok()?)))
+        .collect();
+    // A catalog stands in for the files, so nothing is looked up on disk while planning from one.
+    // Cover art is looked for only when it changes the plan, and the --missing-art options can't
+    // be combined with a catalog.
+    let offline = options.catalog.is_some();
+    let albums = Albums::group(readable.iter().copied(), options.missing_art.is_some());
+
+    let mut readable_idx = 0;
+    let mut entries: Vec<_> = tracks
+        .into_iter()
+        .map(|(source, meta, notes, inferred)| match meta {
+            Ok(meta) => {
+                let track_idx = readable_idx;
+                let album = albums.of(track_idx);
+                readable_idx += 1;
+                let label = album.label();
+                let name = format
+                    .build_name(&meta, album)
+                    .and_then(check_parts)
+                    .and_then(|name| check_depth(name, options.max_depth));
+                let mut entry = match name {
+                    Ok(name) => {
+                        let (name, root) = match &options.missing_art {
+                            Some(MissingArt::Marker(marker)) if !album.has_art => {
+                                (mark_folder(name, marker), mode.destination())
+                            }
+                            Some(MissingArt::Review(dir)) if !album.has_art => (name, Some(&**dir)),
+                            _ => (name, mode.destination()),
+                        };
+                        let target = target_path(&source, name, root);
+                        let status = match mode {
+                            _ if target == source => Status::Unchanged,
+                            Mode::Copy(_) => Status::Copied,
+                            Mode::Rename | Mode::Move(_) => Status::Renamed,
+                        };
+                        Entry {
+                            source,
+                            target: Some(target),
+                            status,
+                            error: None,
+                            duplicate: None,
+                            album: None,
+                            inferred: None,
+                            tags: None,
+                            notes: Vec::new(),
+                        }
+                    }
+                    Err(e) => Entry::failed(source, e),
+                };
+                entry.album = Some(label);
+                entry.notes = notes;
+                entry
+                    .notes
+                    .extend(albums.odd_script(track_idx).map(From::from));
+                entry.inferred = inferred;
+                entry.tags = Some(meta);
+                entry
+            }
+            Err(e) => Entry::failed(source, e),
+        })
+        .collect();
+
+    mark_conflicts(&mut entries, !offline);
+    let library = match mode.destination() {
+        Some(root) if !offline => Some(Library::scan(root)?),
+        _ => None,
+    };
+    if library.is_some() || options.dedupe.is_some() {
+        let dedupe = options.dedupe.unwrap_or(Dedupe::Skip);
+        mark_duplicates(&mut entries, library, dedupe);
+    }
+    if options.on_conflict == OnConflict::Suffix {
+        settle_conflicts(&mut entries, mode, !offline);
+    }
+    guard(&mut entries, options);
+    Ok(entries)
+}
+
+/// Skips changes to protected directories and read-only sources (except when planning from a
+/// catalog, which says nothing about permissions). Runs again after anything that turns entries
+/// into changes, such as answers in an interactive review.
+pub fn guard(entries: &mut [Entry], options: &Options) {
+    if !options.protected.is_empty() {
+        mark_protected(entries, &options.protected, options.catalog.is_none());
+    }
+    if !options.clear_readonly && options.catalog.is_none() {
+        mark_read_only(entries);
+    }
+}
+
+/// Fails a generated name with a part that is empty, `.`, or `..`. Tag values can hold slashes,
+/// and a `..` among them would put the file outside its directory or the destination.
+fn check_parts(name: String) -> Result<String> {
+    if name.split('/').any(|part| matches!(part, "" | "." | "..")) {
+        return Err(Error::UnsafeName(name));
+    }
+    Ok(name)
+}
+
+/// Fails a generated name with more than `max` parts, which usually means a tag was full of
+/// slashes rather than that the template asked for a tree that deep.
+fn check_depth(name: String, max: usize) -> Result<String> {
+    let depth = name.split('/').filter(|part| !part.is_empty()).count();
+    if depth > max {
+        return Err(Error::TooDeep(depth, max));
+    }
+    Ok(name)
+}
+
+/// Reads a file's tags, giving up after `timeout`.
+///
+/// Some damaged files send a parser into a loop. The reader can't be interrupted, so it's left
+/// to finish (or not) on its own thread while the run moves on.
+fn read(path: &Path, timeout: Option<Duration>) -> Result<Meta> {
+    let Some(timeout) = timeout else {
+        return Meta::read(path);
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let owned = path.to_owned();
+    thread::spawn(move || {
+        let _ = tx.send(Meta::read(&owned));
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(meta) => meta,
+        Err(_) => Err(Error::Timeout(timeout)),
+    }
+}
+
+/// Where a file named `name` goes: under `root` when there is one, and otherwise beside the
+/// source.
+///
+/// Names use `/` between directories whatever the platform. Each part is joined on its own,
+/// since `/` isn't a separator in every kind of Windows path.
+fn target_path(source: &Path, name: String, root: Option<&Path>) -> PathBuf {
+    let mut target = match root {
+        Some(root) => walk::destination_dir(root),
+        None => source.parent().unwrap_or(Path::new("")).to_owned(),
+    };
+    target.extend(name.split('/'));
+
+    if let Some(suffix) = meta::suffix(source) {
+        let target = target.as_mut_os_string();
+        target.push(".");
+        target.push(suffix);
+    }
+    target
+}
+
+/// Appends `marker` to the folder a name puts its file in. Names without a folder are left
+/// alone.
+fn mark_folder(name: String, marker: &str) -> String {
+    match name.rfind('/') {
+        Some(slash) => format!("{} {marker}{}", &name[..slash], &name[slash..]),
+        None => name,
+    }
+}
+
+/// Marks renames that collide with each other or, with `on_disk`, with files already on disk.
+///
+/// When two files in the same run want the same name, neither gets it; picking a winner would
+/// depend on argument order.
+fn mark_conflicts(entries: &mut [Entry], on_disk: bool) {
+    let mut claims: HashMap<PathBuf, usize> = HashMap::new();
+    for entry in entries.iter().filter(|e| e.status.is_change()) {
+        let target = entry.target.clone().unwrap();
+        *claims.entry(target).or_default() += 1;
+    }
+
+    for entry in entries.iter_mut() {
+        if !entry.status.is_change() {
+            continue;
+        }
+
+        let target = entry.target.as_deref().unwrap();
+        if claims[target] > 1 {
+            entry.status = Status::Conflict;
+        } else if on_disk && occupied(&entry.source, target) {
+            entry.status = Status::SkippedExisting;
+        }
+    }
+}
+
+/// Gives files that collide numbered names, in plan order.
+///
+/// The first of several files wanting a free target keeps it; the others, and files whose target
+/// is already on disk, get the first free `name (n).ext`.
+fn settle_conflicts(entries: &mut [Entry], mode: &Mode, on_disk: bool) {
+    for idx in 0..entries.len() {
+        let entry = &entries[idx];
+        let target = match entry.status {
+            Status::Conflict | Status::SkippedExisting => entry.target.clone().unwrap(),
+            _ => continue,
+        };
+
+        let claimed = entries
+            .iter()
+            .any(|other| other.status.is_change() && other.target.as_ref() == Some(&target));
+        let taken = on_disk && occupied(&entry.source, &target);
+        if entry.status == Status::SkippedExisting || claimed || taken {
+            let free = free_name(entries, &target, on_disk);
+            entries[idx].target = Some(free);
+        }
+        entries[idx].status = mode.change_status();
+    }
+}
+
+/// The first of `name (2).ext`, `name (3).ext`, ... that isn't planned and, with `on_disk`,
+/// doesn't exist.
+pub fn free_name(entries: &[Entry], target: &Path, on_disk: bool) -> PathBuf {
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let extension = meta::suffix(target).map(|extension| extension.to_string_lossy());
+    let stem = match &extension {
+        Some(extension) => &name[..name.len() - extension.len() - 1],
+        None => &name[..],
+    };
+    (2..)
+        .map(|n| {
+            let name = match &extension {
+                Some(extension) => format!("{stem} ({n}).{extension}"),
+                None => format!("{stem} ({n})"),
+            };
+            target.with_file_name(name)
+        })
+        .find(|candidate| {
+            let planned = entries
+                .iter()
+                .any(|entry| entry.target.as_deref() == Some(candidate.as_path()));
+            let exists = on_disk && candidate.exists();
+            !planned && !exists
+        })
+        .unwrap()
+}
+
+/// Finds files whose content already exists where they're headed.
+///
+/// A file is a duplicate when its target is occupied by a file with the same content, or, when
+/// importing into a library, when the same content sits anywhere else in the library. Either
+/// way, repeating a run is a no-op rather than a pile of conflicts.
+fn mark_duplicates(entries: &mut [Entry], mut library: Option<Library>, dedupe: Dedupe) {
+    for entry in entries.iter_mut() {
+        let duplicate = match (entry.status, &mut library) {
+            (Status::SkippedExisting, _) => {
+                let target = entry.target.as_deref().unwrap();
+                same_content(&entry.source, target).map(|same| same.then(|| target.to_owned()))
+            }
+            (status, Some(library)) if status.is_change() => library.find(&entry.source),
+            _ => continue,
+        };
+
+        match duplicate {
+            Ok(Some(duplicate)) => {
+                entry.status = match dedupe {
+                    Dedupe::Skip => Status::SkippedDuplicate,
+                    Dedupe::Hardlink => Status::Hardlinked,
+                };
+                entry.duplicate = Some(duplicate);
+            }
+            Ok(None) => {}
+            Err(e) => entry.complete(Err(e.into())),
+        }
+    }
+}
+
+/// Finds the protected directory, if any, that a path is inside of. Both are resolved first, so
+/// that a symlink or `..` can't lead around a protected directory. With `on_disk` false, as for
+/// a catalog, paths are only made absolute and tidied, without looking at what's there.
+pub fn protection(protected: &[PathBuf], on_disk: bool) -> impl Fn(&Path) -> Option<PathBuf> + '_ {
+    let resolve = move |path: &Path| {
+        if on_disk {
+            resolve(path)
+        } else {
+            walk::normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_owned()))
+        }
+    };
+    let roots: Vec<_> = protected.iter().map(|root| resolve(root)).collect();
+    move |path| {
+        let path = resolve(path);
+        protected
+            .iter()
+            .zip(&roots)
+            .find(|(_, root)| path.starts_with(root))
+            .map(|(root, _)| root.clone())
+    }
+}
+
+/// Holds back changes that would touch a protected directory.
+///
+/// Renames and moves touch both the source and the target; copies only add to the target, and
+/// hard links only replace the source.
+fn mark_protected(entries: &mut [Entry], protected: &[PathBuf], on_disk: bool) {
+    let inside = protection(protected, on_disk);
+    for entry in entries.iter_mut() {
+        let touched = match entry.status {
+            Status::Hardlinked => vec![&entry.source],
+            Status::Copied => vec![entry.target.as_ref().unwrap()],
+            Status::Renamed => vec![&entry.source, entry.target.as_ref().unwrap()],
+            _ => continue,
+        };
+
+        if let Some(root) = touched.into_iter().find_map(|path| inside(path)) {
+            entry.status = Status::SkippedProtected;
+            entry.error = Some(Error::Protected(root));
+        }
+    }
+}
+
+/// Skips files that can't be moved away from where they are because they or their directories
+/// are read-only. Copies only read their sources, so they go ahead.
+fn mark_read_only(entries: &mut [Entry]) {
+    for entry in entries.iter_mut() {
+        if !matches!(entry.status, Status::Renamed | Status::Hardlinked) {
+            continue;
+        }
+        if let Some(path) = readonly::blocker(&entry.source) {
+            entry.status = Status::SkippedReadOnly;
+            entry.error = Some(Error::ReadOnly(path));
+        }
+    }
+}
+
+/// Roots with fewer files than this are too small for a share of them to mean much.
+const SWEEP_MIN_FILES: usize = 50;
+
+/// Finds a directory root where the plan renames more than `max_percent` of the files found.
+///
+/// A template mistake, such as a missing `/` or a tag most files lack, tends to rename nearly
+/// everything at once, while routine runs over an organized library change little. Returns the
+/// root with the number of files renamed and found under it.
+pub fn sweeping(
+    entries: &[Entry],
+    roots: &[PathBuf],
+    max_percent: u8,
+) -> Option<(PathBuf, usize, usize)> {
+    roots.iter().filter(|root| root.is_dir()).find_map(|root| {
+        let under: Vec<_> = entries
+            .iter()
+            .filter(|entry| entry.source.starts_with(root))
+            .collect();
+        let renamed = under
+            .iter()
+            .filter(|entry| entry.status == Status::Renamed)
+            .count();
+        let too_many = under.len() >= SWEEP_MIN_FILES
+            && renamed * 100 > under.len() * usize::from(max_percent);
+        too_many.then(|| (root.clone(), renamed, under.len()))
+    })
+}
+
+/// How a plan would reshape the directory tree: the number of directories it creates, and the
+/// number of existing directories it moves everything out of. Emptied directories are left in
+/// place, so they're worth knowing about before a template change is applied.
+pub fn churn(entries: &[Entry]) -> (usize, usize) {
+    let targets: Vec<_> = entries
+        .iter()
+        .filter(|entry| entry.status.is_change())
+        .filter_map(|entry| entry.target.as_deref())
+        .collect();
+
+    let mut created = HashSet::new();
+    for target in &targets {
+        for dir in target.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() || dir.exists() || !created.insert(dir) {
+                break;
+            }
+        }
+    }
+
+    // A directory that gains a subdirectory isn't left empty either.
+    let arriving: HashSet<_> = targets
+        .iter()
+        .flat_map(|target| readonly::dir_of(target).ancestors())
+        .collect();
+    let mut leaving: HashMap<_, usize> = HashMap::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.status == Status::Renamed)
+    {
+        *leaving.entry(readonly::dir_of(&entry.source)).or_default() += 1;
+    }
+    let emptied = leaving
+        .into_iter()
+        .filter(|(dir, leaving)| {
+            !arriving.contains(dir) && fs::read_dir(dir).is_ok_and(|dir| dir.count() == *leaving)
+        })
+        .count();
+
+    (created.len(), emptied)
+}
+
+/// An absolute form of `path` with symbolic links resolved, as far as it exists.
+///
+/// Targets usually don't exist yet, so the deepest existing ancestor is resolved and the rest
+/// appended as written.
+fn resolve(path: &Path) -> PathBuf {
+    let mut existing = path;
+    let mut rest = Vec::new();
+    loop {
+        if let Ok(resolved) = fs::canonicalize(existing) {
+            return rest
+                .iter()
+                .rev()
+                .fold(resolved, |path, part| path.join(part));
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                rest.push(name);
+                existing = parent;
+            }
+            _ => return path.to_owned(),
+        }
+    }
+}
+
+/// True when `target` exists and is not simply `source` under another spelling.
+///
+/// A file that already has its proposed name, or one whose name changes only in case on a
+/// case-insensitive filesystem, doesn't conflict with itself.
+fn occupied(source: &Path, target: &Path) -> bool {
+    if source == target || !target.exists() {
+        return false;
+    }
+
+    match (fs::canonicalize(source), fs::canonicalize(target)) {
+        (Ok(source), Ok(target)) => source != target,
+        _ => true,
+    }
+}
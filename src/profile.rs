@@ -0,0 +1,59 @@
+use std::borrow::Cow;
+
+use clap::ValueEnum;
+
+/// The rules a filesystem imposes on names, applied to the text tags put into them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum FsProfile {
+    /// Use tag values as they are; a `/` in a tag starts a new directory.
+    #[default]
+    None,
+    /// Replace the characters no Unix filesystem allows in a name (`/` and NUL).
+    Posix,
+    /// Also replace the characters Windows forbids, trim the trailing dots and spaces it drops,
+    /// and rename the device names it reserves, so that names survive SMB shares and FAT drives.
+    Windows,
+}
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "aux", "con", "nul", "prn", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+impl FsProfile {
+    /// Cleans a tag's value before it goes into a name.
+    pub fn clean_value(self, value: &str) -> Cow<'_, str> {
+        let forbidden = |c: char| match self {
+            FsProfile::None => false,
+            FsProfile::Posix => c == '/' || c == '\0',
+            FsProfile::Windows => c.is_control() || "<>:\"/\\|?*".contains(c),
+        };
+        if value.contains(forbidden) {
+            value.replace(forbidden, "_").into()
+        } else {
+            value.into()
+        }
+    }
+
+    /// Cleans each component of a finished name, for the rules that apply to whole components.
+    pub fn clean_name(self, name: String) -> String {
+        if self != FsProfile::Windows {
+            return name;
+        }
+
+        let components: Vec<String> = name
+            .split('/')
+            .map(|component| {
+                let trimmed = component.trim_end_matches(['.', ' ']);
+                let stem = trimmed.split('.').next().unwrap_or_default();
+                if RESERVED_NAMES.contains(&stem.to_ascii_lowercase().as_str()) {
+                    format!("{stem}_{}", &trimmed[stem.len()..])
+                } else {
+                    trimmed.to_owned()
+                }
+            })
+            .collect();
+        components.join("/")
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// What keeps `source` from being moved away, if anything: on Windows, the file's own read-only
+/// attribute; elsewhere, a directory that can't be written to. Unix doesn't care whether the file
+/// itself is writable, and renaming carries its permissions along.
+pub fn blocker(source: &Path) -> Option<PathBuf> {
+    if cfg!(windows) && fs::metadata(source).is_ok_and(|m| m.permissions().readonly()) {
+        return Some(source.to_owned());
+    }
+    let dir = dir_of(source);
+    (!writable(dir)).then(|| dir.to_owned())
+}
+
+/// The directory holding `path`, which for a bare file name is the current one.
+pub fn dir_of(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Makes `path` writable by its owner, returning the permissions to put back afterwards. Returns
+/// `None` when there was nothing to change.
+pub fn clear(path: &Path) -> io::Result<Option<fs::Permissions>> {
+    let original = fs::metadata(path)?.permissions();
+    let mut writable = original.clone();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        writable.set_mode(original.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    writable.set_readonly(false);
+
+    if writable == original {
+        return Ok(None);
+    }
+    fs::set_permissions(path, writable)?;
+    Ok(Some(original))
+}
+
+#[cfg(unix)]
+fn writable(dir: &Path) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let Ok(dir) = CString::new(dir.as_os_str().as_bytes()) else {
+        return true;
+    };
+    // SAFETY: `dir` is a valid C string that outlives the call.
+    unsafe { libc::access(dir.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+fn writable(_dir: &Path) -> bool {
+    true
+}
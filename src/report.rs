@@ -0,0 +1,144 @@
+use std::{fmt::Write as _, fs, io, path::Path};
+
+use crate::plan::{Entry, Status};
+
+/// Writes a standalone HTML report of the run: a summary of outcomes, each album's files with
+/// their old and new names, and separate lists of conflicts and errors.
+pub fn write(path: &Path, entries: &[Entry], dry_run: bool) -> io::Result<()> {
+    let mut html = String::new();
+    let title = if dry_run {
+        "tagname preview"
+    } else {
+        "tagname report"
+    };
+
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(html, "<title>{title}</title>");
+    html.push_str(STYLE);
+    html.push_str("</head>\n<body>\n");
+    let _ = writeln!(html, "<h1>{title}</h1>");
+
+    summary(&mut html, entries);
+    albums(&mut html, entries);
+
+    let conflicts: Vec<_> = entries
+        .iter()
+        .filter(|e| matches!(e.status, Status::Conflict | Status::SkippedExisting))
+        .collect();
+    if !conflicts.is_empty() {
+        html.push_str("<h2>Conflicts</h2>\n");
+        table(&mut html, &conflicts);
+    }
+
+    let errors: Vec<_> = entries.iter().filter(|e| e.error.is_some()).collect();
+    if !errors.is_empty() {
+        html.push_str("<h2>Errors</h2>\n<table>\n<tr><th>File</th><th>Error</th></tr>\n");
+        for entry in errors {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td></tr>",
+                escape(&entry.source.to_string_lossy()),
+                escape(&entry.error.as_ref().unwrap().to_string()),
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    fs::write(path, html)
+}
+
+const STYLE: &str = "<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.5em; text-align: left; }
+th { background: #eee; }
+.renamed, .copied, .hardlinked { color: #060; }
+.conflict, .skipped_existing, .skipped_duplicate, .skipped_protected, .skipped_partial,
+.skipped_read_only, .skipped_drm, .unsupported_format { color: #a60; }
+.io_error, .skipped_missing_tag, .too_deep, .unsafe_name, .interrupted { color: #a00; }
+.skipped_by_user { color: #666; }
+</style>
+";
+
+fn summary(html: &mut String, entries: &[Entry]) {
+    let mut counts: Vec<(Status, usize)> = Vec::new();
+    for entry in entries {
+        match counts
+            .iter_mut()
+            .find(|(status, _)| *status == entry.status)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((entry.status, 1)),
+        }
+    }
+
+    html.push_str("<h2>Summary</h2>\n<table>\n<tr><th>Status</th><th>Files</th></tr>\n");
+    for (status, count) in counts {
+        let status = status.as_str();
+        let _ = writeln!(
+            html,
+            "<tr><td class=\"{status}\">{status}</td><td>{count}</td></tr>"
+        );
+    }
+    let _ = writeln!(
+        html,
+        "<tr><th>total</th><th>{}</th></tr>\n</table>",
+        entries.len()
+    );
+}
+
+fn albums(html: &mut String, entries: &[Entry]) {
+    let mut albums: Vec<(&str, Vec<&Entry>)> = Vec::new();
+    for entry in entries {
+        let Some(label) = entry.album.as_deref() else {
+            continue;
+        };
+        match albums.iter_mut().find(|(album, _)| *album == label) {
+            Some((_, members)) => members.push(entry),
+            None => albums.push((label, vec![entry])),
+        }
+    }
+
+    if albums.is_empty() {
+        return;
+    }
+
+    html.push_str("<h2>Albums</h2>\n");
+    for (label, members) in albums {
+        let _ = writeln!(html, "<h3>{}</h3>", escape(label));
+        table(html, &members);
+    }
+}
+
+fn table(html: &mut String, entries: &[&Entry]) {
+    html.push_str("<table>\n<tr><th>Before</th><th>After</th><th>Status</th></tr>\n");
+    for entry in entries {
+        let target = entry
+            .target
+            .as_deref()
+            .map(|target| escape(&target.to_string_lossy()))
+            .unwrap_or_default();
+        let status = entry.status.as_str();
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td><td>{target}</td><td class=\"{status}\">{status}</td></tr>",
+            escape(&entry.source.to_string_lossy()),
+        );
+    }
+    html.push_str("</table>\n");
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,128 @@
+use std::{
+    fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use crate::{
+    ignore, json,
+    plan::{Entry, Status},
+    Error, Result,
+};
+
+/// Entry numbers and ranges, such as `1,5,9-20`. Numbers start at one, as in the numbered
+/// preview.
+#[derive(Debug, Clone, Default)]
+pub struct Indices(Vec<RangeInclusive<usize>>);
+
+impl Indices {
+    fn contains(&self, n: usize) -> bool {
+        self.0.iter().any(|range| range.contains(&n))
+    }
+}
+
+pub fn parse_indices(s: &str) -> Result<Indices, String> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let bad = || format!("bad index or range: {part}");
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            let first: usize = first.trim().parse().map_err(|_| bad())?;
+            let last: usize = last.trim().parse().map_err(|_| bad())?;
+            if first == 0 || last < first {
+                return Err(bad());
+            }
+            Ok(first..=last)
+        })
+        .collect::<Result<_, _>>()
+        .map(Indices)
+}
+
+/// Parses a glob that picks entries by path. As in an ignore file, a pattern without a `/`
+/// matches file names, and one with a `/` matches the end of the path.
+pub fn parse_glob(s: &str) -> Result<Regex, String> {
+    Regex::new(&format!("^(?:.*/)?{}$", ignore::glob_to_regex(s)))
+        .map_err(|e| format!("bad pattern: {e}"))
+}
+
+/// A file picked by number from a saved plan, with what that plan said would happen to it.
+pub struct Pick {
+    source: PathBuf,
+    target: Option<PathBuf>,
+    status: String,
+}
+
+/// Looks up the entries numbered by `indices` in a plan saved with `--output json`, so that the
+/// numbers mean what they meant in the preview they were read from.
+pub fn picks(plan: &Path, indices: &Indices) -> Result<Vec<Pick>> {
+    let text = fs::read_to_string(plan)?;
+    let bad = |message: String| Error::Plan(format!("{}: {message}", plan.display()));
+    let document = json::parse(&text).map_err(bad)?;
+    let files = document
+        .get("files")
+        .and_then(json::Value::as_array)
+        .ok_or_else(|| bad("no files list".into()))?;
+    if let Some(last) = indices.0.iter().map(|range| *range.end()).max() {
+        if last > files.len() {
+            return Err(bad(format!("no entry {last}, only {}", files.len())));
+        }
+    }
+
+    files
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| indices.contains(idx + 1))
+        .map(|(_, file)| {
+            let field = |name| file.get(name).and_then(json::Value::as_str);
+            let (Some(source), Some(status)) = (field("source"), field("status")) else {
+                return Err(bad("file without a source or status".into()));
+            };
+            Ok(Pick {
+                source: source.into(),
+                target: field("target").map(PathBuf::from),
+                status: status.into(),
+            })
+        })
+        .collect()
+}
+
+/// Holds back every change in the plan except those picked from a saved plan or by pattern.
+///
+/// A pattern picks an entry when it matches either the file's current path or its planned one.
+/// Entries left out are skipped as though declined interactively. Fails, changing nothing, when
+/// a picked file isn't planned exactly as it was in the saved plan: the files or their tags have
+/// changed since, and the numbers may no longer mean what they did.
+pub fn apply(entries: &mut [Entry], picks: Option<&[Pick]>, patterns: &[Regex]) -> Result<()> {
+    let matches = |path: &Path| {
+        let path: Vec<_> = path
+            .components()
+            .map(|part| part.as_os_str().to_string_lossy())
+            .collect();
+        let path = path.join("/");
+        patterns.iter().any(|rx| rx.is_match(&path))
+    };
+
+    let picks = picks.unwrap_or_default();
+    for pick in picks {
+        let current = entries.iter().find(|entry| entry.source == pick.source);
+        let same = current.is_some_and(|entry| {
+            entry.target == pick.target && entry.status.as_str() == pick.status
+        });
+        if !same {
+            return Err(Error::StalePlan(pick.source.clone()));
+        }
+    }
+
+    for entry in entries.iter_mut() {
+        let picked = picks.iter().any(|pick| pick.source == entry.source)
+            || matches(&entry.source)
+            || entry.target.as_deref().is_some_and(matches);
+        if !picked && (entry.status.is_change() || entry.status == Status::Hardlinked) {
+            entry.status = Status::SkippedByUser;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use id3::TagLike;
+
+use crate::{meta::Meta, mp4, walk, Result};
+
+/// Prints the tags of each file, along with any chapters it carries.
+pub fn run(paths: &[String]) -> Result<()> {
+    let files = walk::expand(paths, &walk::Filter::default())?;
+    for (idx, path) in files.iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        println!("{}", path.display());
+
+        let meta = match Meta::read(path) {
+            Ok(meta) => meta,
+            Err(e) => {
+                println!("  error: {e}");
+                continue;
+            }
+        };
+
+        print_meta(&meta);
+        print_chapters(path);
+    }
+    Ok(())
+}
+
+fn print_meta(meta: &Meta) {
+    let duration = (
+        "Duration",
+        meta.duration.map(|secs| timestamp(secs * 1000.0)),
+    );
+    let fields = meta.fields().into_iter().chain([duration]);
+
+    for (name, value) in fields {
+        if let Some(value) = value {
+            println!("  {name}: {value}");
+        }
+    }
+}
+
+/// Lists chapter start times and titles.
+///
+/// Chapters are read from ID3 CHAP frames, or from an MP4 file's chapter list or chapter track.
+fn print_chapters(path: &Path) {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let chapters: Vec<(f64, String)> = match extension.as_deref() {
+        Some("mp3") => {
+            let Ok(tag) = id3::Tag::read_from_path(path) else {
+                return;
+            };
+            let mut chapters: Vec<_> = tag.chapters().collect();
+            chapters.sort_by_key(|chapter| chapter.start_time);
+            chapters
+                .into_iter()
+                .map(|chapter| {
+                    let title = chapter.title().unwrap_or(&chapter.element_id);
+                    (chapter.start_time.into(), title.to_owned())
+                })
+                .collect()
+        }
+        Some("m4a" | "m4b" | "m4p" | "m4v" | "isom" | "mp4") => match mp4::chapters(path) {
+            Ok(chapters) => chapters
+                .into_iter()
+                .map(|chapter| (chapter.start, chapter.title))
+                .collect(),
+            Err(_) => return,
+        },
+        _ => return,
+    };
+    if chapters.is_empty() {
+        return;
+    }
+
+    println!("  Chapters:");
+    for (start, title) in chapters {
+        println!("    {}  {title}", timestamp(start));
+    }
+}
+
+/// Formats milliseconds as `h:mm:ss`.
+fn timestamp(millis: f64) -> String {
+    let secs = (millis / 1000.0).round() as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,96 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{json, Error, Result};
+
+/// Checks that the filesystem looks the way a saved plan says it should after being applied.
+/// Returns true when every file matches.
+///
+/// The plan is the output of `--output json`, from either a preview or a real run. Renamed files
+/// must exist at their targets and no longer at their sources; copies must exist at their targets;
+/// hard-linked files must share their data with their duplicates. Files the plan left alone are
+/// not checked.
+pub fn run(plan: &Path) -> Result<bool> {
+    let text = fs::read_to_string(plan)?;
+    let bad = |message: String| Error::Plan(format!("{}: {message}", plan.display()));
+    let document = json::parse(&text).map_err(bad)?;
+    let files = document
+        .get("files")
+        .and_then(json::Value::as_array)
+        .ok_or_else(|| bad("no files list".into()))?;
+
+    let mut checked = 0;
+    let mut flagged = 0;
+    for file in files {
+        let field = |name| {
+            file.get(name)
+                .and_then(json::Value::as_str)
+                .map(PathBuf::from)
+        };
+        let (Some(source), Some(status)) = (field("source"), file.get("status")) else {
+            return Err(bad("file without a source or status".into()));
+        };
+        let target = field("target");
+        let duplicate = field("duplicate");
+
+        let problems = match (status.as_str(), &target, &duplicate) {
+            (Some("renamed"), Some(target), _) => renamed(&source, target),
+            (Some("copied"), Some(target), _) => present(target).into_iter().collect(),
+            (Some("hardlinked"), _, Some(duplicate)) => hardlinked(&source, duplicate),
+            _ => continue,
+        };
+
+        checked += 1;
+        if !problems.is_empty() {
+            flagged += 1;
+            println!("{}: {}", source.display(), problems.join("; "));
+        }
+    }
+
+    eprintln!("{flagged} of {checked} planned changes don't match the filesystem");
+    Ok(flagged == 0)
+}
+
+fn renamed(source: &Path, target: &Path) -> Vec<String> {
+    let mut problems: Vec<_> = present(target).into_iter().collect();
+
+    // A case-only rename on a case-insensitive filesystem leaves the source "existing".
+    let moved = match (fs::canonicalize(source), fs::canonicalize(target)) {
+        (Ok(source), Ok(target)) => source == target,
+        (Err(_), _) => true,
+        _ => false,
+    };
+    if !moved {
+        problems.push("source still exists".into());
+    }
+    problems
+}
+
+fn present(target: &Path) -> Option<String> {
+    (!target.exists()).then(|| format!("{} is missing", target.display()))
+}
+
+fn hardlinked(source: &Path, duplicate: &Path) -> Vec<String> {
+    match (fs::metadata(source), fs::metadata(duplicate)) {
+        (Err(_), _) => vec!["file is missing".into()],
+        (_, Err(_)) => vec![format!("{} is missing", duplicate.display())],
+        (Ok(a), Ok(b)) if !same_file(&a, &b) => {
+            vec![format!("not linked to {}", duplicate.display())]
+        }
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Without inode numbers, the best available check is that both names hold the same data.
+#[cfg(not(unix))]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.len() == b.len()
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    ignore::{self, Rule},
+    meta,
+};
+
+/// Decides which files found on the command line or during traversal are worth reading.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Files smaller than this many bytes are ignored.
+    pub min_size: Option<u64>,
+
+    /// Files last modified at or before this time are ignored.
+    pub newer_than: Option<SystemTime>,
+
+    /// Files last modified at or after this time are ignored.
+    pub older_than: Option<SystemTime>,
+
+    /// Don't honor `.tagnameignore` files found while searching directories.
+    pub no_ignore: bool,
+}
+
+impl Filter {
+    fn accepts(&self, path: &Path) -> io::Result<bool> {
+        if self.min_size.is_none() && self.newer_than.is_none() && self.older_than.is_none() {
+            return Ok(true);
+        }
+
+        let metadata = fs::metadata(path)?;
+        if self
+            .min_size
+            .is_some_and(|min_size| metadata.len() < min_size)
+        {
+            return Ok(false);
+        }
+        if self.newer_than.is_some() || self.older_than.is_some() {
+            let modified = metadata.modified()?;
+            if self.newer_than.is_some_and(|time| modified <= time)
+                || self.older_than.is_some_and(|time| modified >= time)
+            {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Expands the command line's paths into the files to process.
+///
+/// Files are taken as given. Directories are searched recursively for audio files, in name
+/// order, so that runs over the same tree always see files in the same order, skipping whatever
+/// `.tagnameignore` files along the way exclude. Paths are
+/// normalized first, and each file is listed once however many of the paths lead to it.
+pub fn expand(paths: &[String], filter: &Filter) -> io::Result<Vec<PathBuf>> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    for path in paths {
+        let path = normalize(Path::new(path));
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+
+        if path.is_dir() {
+            walk(&path, &mut files, filter, &[])?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    // A directory and a file inside it, or two overlapping directories, name some files twice;
+    // so do two mounts of the same filesystem.
+    let cwd = env::current_dir()?;
+    let mut found = HashSet::new();
+    files.retain(|file| found.insert(Identity::of(file, &cwd)));
+
+    let mut accepted = Vec::with_capacity(files.len());
+    for file in files {
+        // Missing files are left for the reader to report.
+        if !file.exists() || filter.accepts(&file)? {
+            accepted.push(file);
+        }
+    }
+    Ok(accepted)
+}
+
+/// What makes two paths the same file for planning.
+#[derive(Debug, PartialEq, Eq, Hash)]
+enum Identity {
+    /// A name in a directory known by its device and inode, which is the same whichever mount
+    /// the directory is reached through. Hard links have names of their own, so each is planned.
+    Entry(u64, u64, OsString),
+    Path(PathBuf),
+}
+
+impl Identity {
+    fn of(file: &Path, cwd: &Path) -> Self {
+        let Ok(canonical) = fs::canonicalize(file) else {
+            return Identity::Path(normalize(&cwd.join(file)));
+        };
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            let dir = canonical.parent().and_then(|dir| fs::metadata(dir).ok());
+            if let (Some(dir), Some(name)) = (dir, canonical.file_name()) {
+                return Identity::Entry(dir.dev(), dir.ino(), name.to_owned());
+            }
+        }
+        Identity::Path(canonical)
+    }
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>, filter: &Filter, rules: &[Rule]) -> io::Result<()> {
+    let mut rules = rules.to_vec();
+    if !filter.no_ignore {
+        rules.extend(ignore::read(dir)?);
+    }
+
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let kind = entry.file_type()?;
+        if ignore::is_ignored(&rules, &path, kind.is_dir()) {
+            continue;
+        }
+
+        if kind.is_dir() {
+            walk(&path, files, filter, &rules)?;
+        } else if kind.is_file() && meta::is_audio(&path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Tidies a path without touching the filesystem: `.` components and trailing separators are
+/// dropped, and `..` cancels the component before it.
+///
+/// Symbolic links aren't resolved, so `link/..` becomes the directory holding `link` rather than
+/// the parent of wherever it points; `--canonicalize` is there for trees where that matters.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normal.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normal.pop();
+                }
+                // `..` at the root is the root.
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normal.push(".."),
+            },
+            component => normal.push(component),
+        }
+    }
+
+    if normal.as_os_str().is_empty() {
+        normal.push(".");
+    }
+    normal
+}
+
+/// The directory a destination names. A bare drive like `D:` means the drive's root rather than
+/// its current directory, which is rarely what a library path intends.
+pub fn destination_dir(root: &Path) -> PathBuf {
+    let mut components = root.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Prefix(_)), None) => root.join(std::path::MAIN_SEPARATOR_STR),
+        _ => root.to_owned(),
+    }
+}
+
+/// Writes a canonical path the way people do.
+///
+/// On Windows, canonical paths come back in the verbatim `\\?\` form, where `/` isn't a
+/// separator and most tools balk. Those are turned back into plain drive and UNC paths
+/// (`\\server\share\...`) unless the path is too long for them.
+#[cfg(windows)]
+pub fn simplify(path: PathBuf) -> PathBuf {
+    use std::path::Prefix;
+
+    let mut components = path.components();
+    let plain = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::VerbatimDisk(drive) => format!("{}:", drive as char),
+            Prefix::VerbatimUNC(server, share) => format!(
+                r"\\{}\{}",
+                server.to_string_lossy(),
+                share.to_string_lossy()
+            ),
+            _ => return path,
+        },
+        _ => return path,
+    };
+
+    let mut plain = PathBuf::from(plain);
+    plain.extend(components);
+    if plain.as_os_str().len() < 260 {
+        plain
+    } else {
+        path
+    }
+}
+
+#[cfg(not(windows))]
+pub fn simplify(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Parses a size such as `4096`, `500k`, or `1.5M`. Suffixes are binary multiples.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("unknown size unit: {unit}")),
+    };
+
+    let number: f64 = number.parse().map_err(|_| format!("bad size: {s}"))?;
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Parses a point in time: an age such as `90m`, `12h`, `7d`, or `2w` counted back from now, or a
+/// UTC date such as `2024-05-01` or `2024-05-01T18:30:00`.
+pub fn parse_time(s: &str) -> Result<SystemTime, String> {
+    let s = s.trim();
+    if let Some(age) = parse_age(s) {
+        return SystemTime::now()
+            .checked_sub(age)
+            .ok_or_else(|| format!("too long ago: {s}"));
+    }
+
+    let bad = || format!("bad time (expected an age like 7d or a date like 2024-05-01): {s}");
+    let (date, time) = s.split_once(['T', ' ']).unwrap_or((s, "00:00:00"));
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|part| part.parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    let mut time: Vec<i64> = time
+        .trim_end_matches('Z')
+        .split(':')
+        .map(|part| part.parse().map_err(|_| bad()))
+        .collect::<Result<_, _>>()?;
+    time.resize(3, 0);
+
+    let &[year, month, day] = &date[..] else {
+        return Err(bad());
+    };
+    let (hour, minute, second) = (time[0], time[1], time[2]);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..=60).contains(&second)
+    {
+        return Err(bad());
+    }
+
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    let secs = u64::try_from(secs).map_err(|_| format!("before 1970: {s}"))?;
+    Ok(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Parses an age such as `45s`, `90m`, `12h`, `7d`, or `2w`.
+fn parse_age(s: &str) -> Option<Duration> {
+    let split = s.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(number.checked_mul(multiplier)?))
+}
+
+/// Days from 1970-01-01 to a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}